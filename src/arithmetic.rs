@@ -0,0 +1,613 @@
+use thiserror::Error;
+
+use crate::{is_valid_name, Shell};
+
+const MAXIMUM_DEPTH: usize = 1024;
+
+const OPERATORS: [&str; 37] = [
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=",
+    "%=", "+=", "-=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^", "!", "~",
+    "?", ":", ",", "=",
+];
+
+const ASSIGNMENTS: [&str; 11] = [
+    "=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ArithmeticError {
+    #[error("division by 0")]
+    DivisionByZero,
+    #[error("arithmetic overflow")]
+    Overflow,
+    #[error("exponent less than 0")]
+    NegativeExponent,
+    #[error("expression recursion level exceeded")]
+    TooDeep,
+    #[error("value too great for base (error token is \"{0}\")")]
+    InvalidNumber(String),
+    #[error("syntax error in expression (error token is \"{0}\")")]
+    Syntax(String),
+    #[error("attempted assignment to non-variable (error token is \"{0}\")")]
+    NotAVariable(String),
+    #[error("{0}")]
+    Variable(String),
+}
+
+pub type ArithmeticResult<T> = Result<T, ArithmeticError>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Operator(&'static str),
+    LeftParenthesis,
+    RightParenthesis,
+}
+
+fn tokenize(expression: &str) -> ArithmeticResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+
+    while let Some(character) = rest.chars().next() {
+        if character.is_whitespace() {
+            rest = &rest[character.len_utf8()..];
+            continue;
+        }
+
+        let length = if character.is_ascii_alphanumeric() || character == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '#'))
+                .unwrap_or(rest.len());
+
+            let word = rest[..length].to_string();
+            if character.is_ascii_digit() {
+                tokens.push(Token::Number(word));
+            } else {
+                tokens.push(Token::Name(word));
+            }
+
+            length
+        } else if character == '(' {
+            tokens.push(Token::LeftParenthesis);
+            1
+        } else if character == ')' {
+            tokens.push(Token::RightParenthesis);
+            1
+        } else if let Some(operator) = OPERATORS.iter().find(|&&op| rest.starts_with(op)) {
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        } else {
+            return Err(ArithmeticError::Syntax(rest.to_string()));
+        };
+
+        rest = &rest[length..];
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> ArithmeticResult<i64> {
+    let invalid = || ArithmeticError::InvalidNumber(text.to_string());
+
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        match base.parse::<u32>() {
+            Ok(base) if (2..=36).contains(&base) => (base, digits),
+            _ => return Err(invalid()),
+        }
+    } else if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut value: i64 = 0;
+    for character in digits.chars() {
+        let digit = character.to_digit(base).ok_or_else(invalid)?;
+
+        value = value
+            .checked_mul(base as i64)
+            .and_then(|value| value.checked_add(digit as i64))
+            .ok_or(ArithmeticError::Overflow)?;
+    }
+
+    Ok(value)
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+    Assign(&'static str, String, Box<Expression>),
+    Increment {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+}
+
+impl Drop for Expression {
+    /// Frees the left operands of long binary chains in a loop, as dropping them
+    /// recursively could overflow the stack.
+    fn drop(&mut self) {
+        let Expression::Binary(_, left, _) = self else {
+            return;
+        };
+
+        let mut next = std::mem::replace(left.as_mut(), Expression::Number(0));
+        while let Expression::Binary(_, left, _) = &mut next {
+            next = std::mem::replace(left.as_mut(), Expression::Number(0));
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Operator(operator)) => Some(operator),
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self) -> ArithmeticError {
+        let rest: Vec<String> = self.tokens[self.index.min(self.tokens.len())..]
+            .iter()
+            .map(|token| match token {
+                Token::Number(text) | Token::Name(text) => text.clone(),
+                Token::Operator(operator) => operator.to_string(),
+                Token::LeftParenthesis => "(".into(),
+                Token::RightParenthesis => ")".into(),
+            })
+            .collect();
+
+        ArithmeticError::Syntax(rest.join(" "))
+    }
+
+    fn expect_operator(&mut self, expected: &str) -> ArithmeticResult<()> {
+        if self.peek_operator() != Some(expected) {
+            return Err(self.unexpected());
+        }
+
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Parses a nested operand, failing before deep nesting can overflow the stack.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Parser) -> ArithmeticResult<Expression>,
+    ) -> ArithmeticResult<Expression> {
+        if self.depth >= MAXIMUM_DEPTH {
+            return Err(ArithmeticError::TooDeep);
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    fn parse_comma(&mut self) -> ArithmeticResult<Expression> {
+        let mut left = self.parse_assignment()?;
+
+        while self.peek_operator() == Some(",") {
+            self.index += 1;
+
+            let right = self.parse_assignment()?;
+            left = Expression::Binary(",", Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_assignment(&mut self) -> ArithmeticResult<Expression> {
+        if let (Some(Token::Name(name)), Some(Token::Operator(operator))) =
+            (self.peek(), self.tokens.get(self.index + 1))
+        {
+            if ASSIGNMENTS.contains(operator) {
+                let name = name.clone();
+                let operator = *operator;
+                self.index += 2;
+
+                let value = self.nested(Parser::parse_assignment)?;
+                return Ok(Expression::Assign(operator, name, Box::new(value)));
+            }
+        }
+
+        let condition = self.parse_conditional()?;
+
+        if let Some(operator) = self.peek_operator() {
+            if ASSIGNMENTS.contains(&operator) {
+                return Err(ArithmeticError::NotAVariable(operator.to_string()));
+            }
+        }
+
+        Ok(condition)
+    }
+
+    fn parse_conditional(&mut self) -> ArithmeticResult<Expression> {
+        let condition = self.parse_binary(0)?;
+
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.index += 1;
+
+        let when_true = self.nested(Parser::parse_comma)?;
+        self.expect_operator(":")?;
+        let when_false = self.nested(Parser::parse_conditional)?;
+
+        Ok(Expression::Conditional(
+            Box::new(condition),
+            Box::new(when_true),
+            Box::new(when_false),
+        ))
+    }
+
+    fn precedence(operator: &str) -> Option<usize> {
+        let precedence = match operator {
+            "||" => 0,
+            "&&" => 1,
+            "|" => 2,
+            "^" => 3,
+            "&" => 4,
+            "==" | "!=" => 5,
+            "<" | ">" | "<=" | ">=" => 6,
+            "<<" | ">>" => 7,
+            "+" | "-" => 8,
+            "*" | "/" | "%" => 9,
+            _ => return None,
+        };
+
+        Some(precedence)
+    }
+
+    fn parse_binary(&mut self, minimum: usize) -> ArithmeticResult<Expression> {
+        let mut left = self.parse_power()?;
+
+        while let Some(operator) = self.peek_operator() {
+            let Some(precedence) = Parser::precedence(operator) else {
+                break;
+            };
+
+            if precedence < minimum {
+                break;
+            }
+            self.index += 1;
+
+            let right = self.parse_binary(precedence + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_power(&mut self) -> ArithmeticResult<Expression> {
+        let base = self.parse_unary()?;
+
+        if self.peek_operator() != Some("**") {
+            return Ok(base);
+        }
+        self.index += 1;
+
+        let exponent = self.nested(Parser::parse_power)?;
+        Ok(Expression::Binary("**", Box::new(base), Box::new(exponent)))
+    }
+
+    fn parse_unary(&mut self) -> ArithmeticResult<Expression> {
+        match self.peek_operator() {
+            Some(operator @ ("++" | "--")) => {
+                self.index += 1;
+
+                let Some(Token::Name(name)) = self.peek().cloned() else {
+                    return Err(self.unexpected());
+                };
+                self.index += 1;
+
+                Ok(Expression::Increment {
+                    name,
+                    delta: if operator == "++" { 1 } else { -1 },
+                    prefix: true,
+                })
+            }
+            Some(operator @ ("!" | "~" | "-" | "+")) => {
+                self.index += 1;
+
+                let operand = self.nested(Parser::parse_unary)?;
+                Ok(Expression::Unary(operator, Box::new(operand)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> ArithmeticResult<Expression> {
+        let primary = self.parse_primary()?;
+
+        if let Expression::Variable(name) = &primary {
+            if let Some(operator @ ("++" | "--")) = self.peek_operator() {
+                self.index += 1;
+
+                return Ok(Expression::Increment {
+                    name: name.clone(),
+                    delta: if operator == "++" { 1 } else { -1 },
+                    prefix: false,
+                });
+            }
+        }
+
+        Ok(primary)
+    }
+
+    fn parse_primary(&mut self) -> ArithmeticResult<Expression> {
+        let expression = match self.peek().cloned() {
+            Some(Token::Number(text)) => Expression::Number(parse_number(&text)?),
+            Some(Token::Name(name)) if is_valid_name(&name) => Expression::Variable(name),
+            Some(Token::LeftParenthesis) => {
+                self.index += 1;
+                let inner = self.nested(Parser::parse_comma)?;
+
+                if self.peek() != Some(&Token::RightParenthesis) {
+                    return Err(self.unexpected());
+                }
+
+                inner
+            }
+            _ => return Err(self.unexpected()),
+        };
+
+        self.index += 1;
+        Ok(expression)
+    }
+}
+
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    depth: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn evaluate_text(&mut self, expression: &str) -> ArithmeticResult<i64> {
+        if self.depth >= MAXIMUM_DEPTH {
+            return Err(ArithmeticError::TooDeep);
+        }
+
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            return Ok(0);
+        }
+
+        let mut parser = Parser {
+            tokens,
+            index: 0,
+            depth: 0,
+        };
+        let expression = parser.parse_comma()?;
+
+        if parser.index < parser.tokens.len() {
+            return Err(parser.unexpected());
+        }
+
+        self.depth += 1;
+        let result = self.evaluate(&expression);
+        self.depth -= 1;
+
+        result
+    }
+
+    fn read(&mut self, name: &str) -> ArithmeticResult<i64> {
+        match self.shell.get_parameter(name) {
+            Some(value) if !value.trim().is_empty() => self.evaluate_text(&value),
+            _ => Ok(0),
+        }
+    }
+
+    fn write(&mut self, name: &str, value: i64) -> ArithmeticResult<i64> {
+        self.shell
+            .variables
+            .set(name, value.to_string())
+            .map_err(ArithmeticError::Variable)?;
+
+        Ok(value)
+    }
+
+    fn evaluate(&mut self, expression: &Expression) -> ArithmeticResult<i64> {
+        match expression {
+            Expression::Number(value) => Ok(*value),
+            Expression::Variable(name) => self.read(name),
+            Expression::Unary(operator, operand) => {
+                let value = self.evaluate(operand)?;
+
+                match *operator {
+                    "!" => Ok((value == 0) as i64),
+                    "~" => Ok(!value),
+                    "-" => value.checked_neg().ok_or(ArithmeticError::Overflow),
+                    _ => Ok(value),
+                }
+            }
+            Expression::Binary(..) => {
+                // a chain like `1 + 2 + ... + n` is a tree as deep as it is long, so walk its
+                // left operands in a loop rather than recursing into each of them
+                let mut chain = Vec::new();
+                let mut leftmost = expression;
+                while let Expression::Binary(operator, left, right) = leftmost {
+                    chain.push((*operator, right));
+                    leftmost = left;
+                }
+
+                let mut value = self.evaluate(leftmost)?;
+                for (operator, right) in chain.into_iter().rev() {
+                    value = match operator {
+                        "&&" => (value != 0 && self.evaluate(right)? != 0) as i64,
+                        "||" => (value != 0 || self.evaluate(right)? != 0) as i64,
+                        _ => apply(operator, value, self.evaluate(right)?)?,
+                    };
+                }
+
+                Ok(value)
+            }
+            Expression::Assign(operator, name, value) => {
+                let value = self.evaluate(value)?;
+
+                let value = match operator.strip_suffix('=') {
+                    Some("") | None => value,
+                    Some(operator) => apply(operator, self.read(name)?, value)?,
+                };
+
+                self.write(name, value)
+            }
+            Expression::Increment {
+                name,
+                delta,
+                prefix,
+            } => {
+                let previous = self.read(name)?;
+                let next = previous
+                    .checked_add(*delta)
+                    .ok_or(ArithmeticError::Overflow)?;
+
+                self.write(name, next)?;
+                Ok(if *prefix { next } else { previous })
+            }
+            Expression::Conditional(condition, when_true, when_false) => {
+                if self.evaluate(condition)? != 0 {
+                    self.evaluate(when_true)
+                } else {
+                    self.evaluate(when_false)
+                }
+            }
+        }
+    }
+}
+
+fn apply(operator: &str, left: i64, right: i64) -> ArithmeticResult<i64> {
+    let result = match operator {
+        "," => Some(right),
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return Err(ArithmeticError::DivisionByZero),
+        "/" => left.checked_div(right),
+        "%" => left.checked_rem(right),
+        "**" if right < 0 => return Err(ArithmeticError::NegativeExponent),
+        "**" => u32::try_from(right)
+            .ok()
+            .and_then(|right| left.checked_pow(right)),
+        "<<" => Some(left.wrapping_shl(right as u32)),
+        ">>" => Some(left.wrapping_shr(right as u32)),
+        "&" => Some(left & right),
+        "|" => Some(left | right),
+        "^" => Some(left ^ right),
+        "<" => Some((left < right) as i64),
+        ">" => Some((left > right) as i64),
+        "<=" => Some((left <= right) as i64),
+        ">=" => Some((left >= right) as i64),
+        "==" => Some((left == right) as i64),
+        "!=" => Some((left != right) as i64),
+        _ => return Err(ArithmeticError::Syntax(operator.to_string())),
+    };
+
+    result.ok_or(ArithmeticError::Overflow)
+}
+
+pub fn evaluate_arithmetic(shell: &mut Shell, expression: &str) -> ArithmeticResult<i64> {
+    Evaluator { shell, depth: 0 }.evaluate_text(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str) -> ArithmeticResult<i64> {
+        evaluate_arithmetic(&mut Shell::new(), expression)
+    }
+
+    #[test]
+    fn follows_precedence_and_assignments() {
+        assert_eq!(evaluate("1 + 2 * 3 ** 2"), Ok(19));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+        assert_eq!(evaluate("x = 3, x += 2, x * 2"), Ok(10));
+        assert_eq!(evaluate("0 ? 1 : 2 > 1"), Ok(1));
+    }
+
+    #[test]
+    fn reads_numbers_in_other_bases() {
+        assert_eq!(evaluate("010 + 0x1f + 2#101 + 36#z"), Ok(79));
+        assert_eq!(
+            evaluate("08"),
+            Err(ArithmeticError::InvalidNumber("08".to_string()))
+        );
+    }
+
+    #[test]
+    fn reports_overflow_instead_of_panicking() {
+        assert_eq!(evaluate("-9223372036854775807 - 1"), Ok(i64::MIN));
+        assert_eq!(
+            evaluate("(-9223372036854775807 - 1) / -1"),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(
+            evaluate("(-9223372036854775807 - 1) % -1"),
+            Err(ArithmeticError::Overflow)
+        );
+        assert_eq!(evaluate("2 ** 63"), Err(ArithmeticError::Overflow));
+        assert_eq!(evaluate("2 ** 62"), Ok(1 << 62));
+    }
+
+    #[test]
+    fn limits_nesting_instead_of_overflowing_the_stack() {
+        // The shell parses on the main thread, whose stack is larger than
+        // the one test threads get by default.
+        let deep = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(|| {
+                let parentheses = format!("{}1{}", "(".repeat(3000), ")".repeat(3000));
+                [
+                    evaluate(&parentheses),
+                    evaluate(&format!("{}1", "!".repeat(3000))),
+                    evaluate(&format!("{}1", "x=".repeat(3000))),
+                ]
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        for result in deep {
+            assert_eq!(result, Err(ArithmeticError::TooDeep));
+        }
+
+        let nested = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        assert_eq!(evaluate(&nested), Ok(1));
+    }
+
+    #[test]
+    fn evaluates_long_chains() {
+        let sum = vec!["1"; 100_000].join(" + ");
+        assert_eq!(evaluate(&sum), Ok(100_000));
+        assert_eq!(evaluate(&vec!["1"; 100_000].join(" && ")), Ok(1));
+    }
+
+    #[test]
+    fn rejects_invalid_operands() {
+        assert_eq!(evaluate("1 / 0"), Err(ArithmeticError::DivisionByZero));
+        assert_eq!(evaluate("2 ** -1"), Err(ArithmeticError::NegativeExponent));
+    }
+}
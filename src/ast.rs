@@ -0,0 +1,127 @@
+#[derive(Debug, Clone)]
+pub enum RedirectTarget {
+    Input { path: String },
+    Output { path: String, append: bool },
+    ReadWrite { path: String },
+    Duplicate { word: String },
+    HereDocument { body: String, expand: bool },
+    HereString { word: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: u32,
+    pub target: RedirectTarget,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub arguments: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IfClause {
+    pub branches: Vec<(List, List)>,
+    pub otherwise: Option<List>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopClause {
+    pub condition: List,
+    pub body: List,
+    pub until: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForClause {
+    pub name: String,
+    pub words: Option<Vec<String>>,
+    pub body: List,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArithmeticForClause {
+    pub initialize: String,
+    pub condition: String,
+    pub update: String,
+    pub body: List,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaseTerminator {
+    Break,
+    FallThrough,
+    Continue,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseItem {
+    pub patterns: Vec<String>,
+    pub body: List,
+    pub terminator: CaseTerminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseClause {
+    pub word: String,
+    pub items: Vec<CaseItem>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CompoundCommand {
+    BraceGroup(List),
+    Subshell(List),
+    Arithmetic(String),
+    If(IfClause),
+    Loop(LoopClause),
+    For(ForClause),
+    ArithmeticFor(ArithmeticForClause),
+    Case(CaseClause),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: Box<Command>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Compound(CompoundCommand, Vec<Redirect>),
+    FunctionDefinition(FunctionDefinition),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    pub negated: bool,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    pub background: bool,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct List {
+    pub items: Vec<AndOrList>,
+}
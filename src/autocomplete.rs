@@ -1,5 +1,5 @@
 use std::{
-    env, fs,
+    fs,
     io::{self, Write},
     vec::Vec,
 };
@@ -9,8 +9,11 @@ use std::os::unix::fs::PermissionsExt;
 
 use crate::Shell;
 
-pub fn prompt() {
-    io::stdout().write("$ ".as_bytes()).unwrap();
+pub const PRIMARY_PROMPT: &str = "$ ";
+pub const CONTINUATION_PROMPT: &str = "> ";
+
+pub fn prompt(text: &str) {
+    io::stdout().write(text.as_bytes()).unwrap();
     io::stdout().flush().unwrap();
 }
 
@@ -66,7 +69,12 @@ fn find_shared_prefix(candidates: &Vec<String>) -> String {
     return first[..end].to_string();
 }
 
-pub fn autocomplete(shell: &Shell, line: &mut String, bell_rang: bool) -> AutocompleteResult {
+pub fn autocomplete(
+    shell: &Shell,
+    prompt_text: &str,
+    line: &mut String,
+    bell_rang: bool,
+) -> AutocompleteResult {
     let mut candidates: Vec<String> = Vec::new();
 
     for key in shell.builtins.keys() {
@@ -76,7 +84,7 @@ pub fn autocomplete(shell: &Shell, line: &mut String, bell_rang: bool) -> Autoco
         }
     }
 
-    if let Ok(paths) = env::var("PATH") {
+    if let Some(paths) = shell.variables.get("PATH") {
         for directory in paths.split(":") {
             if let Ok(entries) = fs::read_dir(directory) {
                 for entry in entries {
@@ -137,7 +145,7 @@ pub fn autocomplete(shell: &Shell, line: &mut String, bell_rang: bool) -> Autoco
         }
 
         io::stdout().write(&[b'\n']).unwrap();
-        prompt();
+        prompt(prompt_text);
         io::stdout().write(&line.as_bytes()).unwrap();
         io::stdout().flush().unwrap();
     }
@@ -0,0 +1,261 @@
+const OPEN_BRACE: char = '{';
+const CLOSE_BRACE: char = '}';
+const COMMA: char = ',';
+const SINGLE: char = '\'';
+const DOUBLE: char = '"';
+const BACKTICK: char = '`';
+const BACKSLASH: char = '\\';
+const DOLLAR: char = '$';
+
+/// A character of a raw word, and whether it is outside of any quote, escape or `$` expansion.
+struct Scanned {
+    index: usize,
+    character: char,
+    active: bool,
+}
+
+fn scan(word: &str) -> Vec<Scanned> {
+    let characters: Vec<(usize, char)> = word.char_indices().collect();
+
+    let mut scanned = Vec::with_capacity(characters.len());
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut dollar = false;
+    let mut nesting: Vec<(char, char)> = Vec::new();
+
+    for &(index, character) in &characters {
+        let active = quote.is_none() && !escaped && nesting.is_empty();
+        scanned.push(Scanned {
+            index,
+            character,
+            active,
+        });
+
+        let after_dollar = std::mem::take(&mut dollar);
+
+        if escaped {
+            escaped = false;
+        } else if let Some(open) = quote {
+            if character == open {
+                quote = None;
+            } else if character == BACKSLASH && open != SINGLE {
+                escaped = true;
+            }
+        } else {
+            match character {
+                BACKSLASH => escaped = true,
+                SINGLE | DOUBLE | BACKTICK => quote = Some(character),
+                DOLLAR => dollar = true,
+                OPEN_BRACE if after_dollar => nesting.push((OPEN_BRACE, CLOSE_BRACE)),
+                '(' if after_dollar => nesting.push(('(', ')')),
+                _ => match nesting.last() {
+                    Some(&(_, close)) if character == close => {
+                        nesting.pop();
+                    }
+                    Some(&(open, close)) if character == open => nesting.push((open, close)),
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    scanned
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    text.parse().ok()
+}
+
+fn is_padded(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+fn sequence(inner: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = inner.split("..").collect();
+
+    let step = match parts.len() {
+        2 => 1,
+        3 => parse_number(parts[2])?.checked_abs()?.max(1),
+        _ => return None,
+    };
+
+    let (start, end) = (parts[0], parts[1]);
+
+    if let (Some(first), Some(last)) = (parse_number(start), parse_number(end)) {
+        let width = if is_padded(start) || is_padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        let mut items = Vec::new();
+        let mut current = first;
+        loop {
+            if current < 0 {
+                items.push(format!(
+                    "-{:0width$}",
+                    current.unsigned_abs(),
+                    width = width.saturating_sub(1)
+                ));
+            } else {
+                items.push(format!("{:0width$}", current, width = width));
+            }
+
+            let next = if first <= last {
+                current.checked_add(step).filter(|&next| next <= last)
+            } else {
+                current.checked_sub(step).filter(|&next| next >= last)
+            };
+
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+
+        return Some(items);
+    }
+
+    let mut start_characters = start.chars();
+    let mut end_characters = end.chars();
+    let (Some(first), None, Some(last), None) = (
+        start_characters.next(),
+        start_characters.next(),
+        end_characters.next(),
+        end_characters.next(),
+    ) else {
+        return None;
+    };
+
+    let (low, high) = (first.min(last) as u32, first.max(last) as u32);
+    let mut codes: Vec<u32> = (low..=high).step_by(step as usize).collect();
+    if first > last {
+        codes = (low..=high).rev().step_by(step as usize).collect();
+    }
+
+    let items = codes
+        .into_iter()
+        .filter_map(char::from_u32)
+        .map(|character| {
+            if character.is_alphanumeric() {
+                character.to_string()
+            } else {
+                format!("{}{}", BACKSLASH, character)
+            }
+        })
+        .collect();
+
+    Some(items)
+}
+
+/// Finds the first brace expression, returning its byte range and the raw alternatives it holds.
+fn find_expression(word: &str) -> Option<(usize, usize, Vec<String>)> {
+    let scanned = scan(word);
+
+    for (position, open) in scanned.iter().enumerate() {
+        if !open.active || open.character != OPEN_BRACE {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+
+        for inner in &scanned[position..] {
+            if !inner.active {
+                continue;
+            }
+
+            match inner.character {
+                OPEN_BRACE => depth += 1,
+                CLOSE_BRACE => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        close = Some(inner.index);
+                        break;
+                    }
+                }
+                COMMA if depth == 1 => commas.push(inner.index),
+                _ => {}
+            }
+        }
+
+        let Some(close) = close else {
+            continue;
+        };
+
+        let start = open.index + OPEN_BRACE.len_utf8();
+
+        if !commas.is_empty() {
+            let mut alternatives = Vec::new();
+            let mut from = start;
+
+            for comma in commas {
+                alternatives.push(word[from..comma].to_string());
+                from = comma + COMMA.len_utf8();
+            }
+            alternatives.push(word[from..close].to_string());
+
+            return Some((open.index, close, alternatives));
+        }
+
+        if let Some(alternatives) = sequence(&word[start..close]) {
+            return Some((open.index, close, alternatives));
+        }
+    }
+
+    None
+}
+
+/// Performs brace expansion on a raw word, before any other expansion takes place.
+pub fn expand_braces(word: &str) -> Vec<String> {
+    let Some((open, close, alternatives)) = find_expression(word) else {
+        return vec![word.to_string()];
+    };
+
+    let prefix = &word[..open];
+    let suffixes = expand_braces(&word[close + CLOSE_BRACE.len_utf8()..]);
+
+    let mut words = Vec::new();
+    for alternative in alternatives {
+        for middle in expand_braces(&alternative) {
+            for suffix in &suffixes {
+                words.push(format!("{}{}{}", prefix, middle, suffix));
+            }
+        }
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_alternatives() {
+        assert_eq!(expand_braces("a{b,c{d,e}}f"), ["abf", "acdf", "acef"]);
+        assert_eq!(
+            expand_braces("{1..3}{a,b}"),
+            ["1a", "1b", "2a", "2b", "3a", "3b"]
+        );
+        assert_eq!(expand_braces("{a,}"), ["a", ""]);
+        assert_eq!(expand_braces("{a}"), ["{a}"]);
+    }
+
+    #[test]
+    fn expands_sequences_with_steps() {
+        assert_eq!(expand_braces("{01..10..3}"), ["01", "04", "07", "10"]);
+        assert_eq!(expand_braces("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(expand_braces("{5..1}"), ["5", "4", "3", "2", "1"]);
+        assert_eq!(expand_braces("{-3..3..2}"), ["-3", "-1", "1", "3"]);
+    }
+}
@@ -1,111 +1,840 @@
-use crate::{RedirectStreams, Shell, ShellCommand};
-use std::{
-    collections::HashMap,
-    env,
-    option::Option,
-    path::{Path, PathBuf},
+use crate::{
+    describe_error, format_job, is_valid_name, quote_word, signal_names, wait_for, wait_foreground,
+    Flow, Job, JobState, RedirectStreams, Shell, ShellCommand, TrapCondition, STATUS_NOT_FOUND,
 };
+use std::{collections::HashMap, env, io, option::Option};
 
-pub type BuiltinFunction = fn(&mut Shell, &Vec<String>, &mut RedirectStreams) -> Option<i32>;
+pub enum BuiltinResult {
+    Status(i32),
+    Exit(i32),
+}
+
+pub const SUCCESS: BuiltinResult = BuiltinResult::Status(0);
+pub const FAILURE: BuiltinResult = BuiltinResult::Status(1);
+
+pub type BuiltinFunction = fn(&mut Shell, &[String], &mut RedirectStreams) -> BuiltinResult;
 pub type BuiltinMap = HashMap<String, BuiltinFunction>;
 
-pub fn builtin_exit(_: &mut Shell, _: &Vec<String>, _: &mut RedirectStreams) -> Option<i32> {
-    Some(0)
+/// Reports that `name` could not write its output, which makes it fail.
+fn write_error(io: &mut RedirectStreams, name: &str, error: &io::Error) -> BuiltinResult {
+    let _ = io.println_error(format!("{}: write error: {}", name, describe_error(error)).as_str());
+    FAILURE
+}
+
+pub fn builtin_exit(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    match arguments.get(1) {
+        None => BuiltinResult::Exit(shell.last_status),
+        Some(value) => match value.parse::<i32>() {
+            Ok(code) => BuiltinResult::Exit(code & 0xff),
+            Err(_) => {
+                let _ = io
+                    .println_error(format!("exit: {}: numeric argument required", value).as_str());
+                BuiltinResult::Exit(2)
+            }
+        },
+    }
 }
 
 pub fn builtin_echo(
     _: &mut Shell,
-    arguments: &Vec<String>,
+    arguments: &[String],
     io: &mut RedirectStreams,
-) -> Option<i32> {
-    io.println(format!("{}", arguments[1..].join(" ")).as_str());
+) -> BuiltinResult {
+    if let Err(error) = io.println(format!("{}", arguments[1..].join(" ")).as_str()) {
+        return write_error(io, "echo", &error);
+    }
 
-    None
+    SUCCESS
 }
 
 pub fn builtin_type(
     shell: &mut Shell,
-    arguments: &Vec<String>,
+    arguments: &[String],
     io: &mut RedirectStreams,
-) -> Option<i32> {
+) -> BuiltinResult {
     let program = &arguments[1];
 
-    match shell.query(program) {
-        ShellCommand::Builtin(_) => io.println(format!("{} is a shell builtin", program).as_str()),
-        ShellCommand::Executable(path) => {
-            io.println(format!("{} is {}", program, path.to_str().unwrap()).as_str())
-        }
-        ShellCommand::None => io.println(format!("{}: not found", program).as_str()),
-    }
+    let (line, result) = match shell.query(program) {
+        ShellCommand::Function(_) => (format!("{} is a function", program), SUCCESS),
+        ShellCommand::Builtin(_) => (format!("{} is a shell builtin", program), SUCCESS),
+        ShellCommand::Executable(path) => (
+            format!("{} is {}", program, path.to_str().unwrap()),
+            SUCCESS,
+        ),
+        ShellCommand::None => (format!("{}: not found", program), FAILURE),
+    };
 
-    None
+    match io.println(line.as_str()) {
+        Ok(()) => result,
+        Err(error) => write_error(io, "type", &error),
+    }
 }
 
-pub fn builtin_pwd(_: &mut Shell, _: &Vec<String>, io: &mut RedirectStreams) -> Option<i32> {
+pub fn builtin_pwd(_: &mut Shell, _: &[String], io: &mut RedirectStreams) -> BuiltinResult {
     match env::current_dir() {
-        Err(e) => io.println_error(format!("pwd: {}", e).as_str()),
-        Ok(path) => io.println(format!("{}", path.to_str().unwrap()).as_str()),
+        Err(e) => {
+            let _ = io.println_error(format!("pwd: {}", e).as_str());
+            FAILURE
+        }
+        Ok(path) => match io.println(format!("{}", path.to_str().unwrap()).as_str()) {
+            Ok(()) => SUCCESS,
+            Err(error) => write_error(io, "pwd", &error),
+        },
     }
-
-    None
 }
 
-pub fn builtin_cd(_: &mut Shell, arguments: &Vec<String>, io: &mut RedirectStreams) -> Option<i32> {
-    let mut absolute_path: Option<PathBuf> = None;
-
-    let path = &arguments[1];
-    if path.starts_with("/") {
-        absolute_path = Some(Path::new(&path).into());
-    } else if path.starts_with(".") {
-        if let Ok(current_path) = env::current_dir() {
-            absolute_path = Some(Path::new(&current_path).join(path));
-        }
-    } else if path.starts_with("~") {
-        match env::var("HOME") {
-            Ok(home) => {
-                absolute_path = Some(Path::new(&home).join(format!("./{}", &path[1..])));
+pub fn builtin_cd(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let (path, announce) = match arguments.get(1).map(String::as_str) {
+        None => match shell.variables.get("HOME") {
+            Some(home) => (home.clone(), false),
+            None => {
+                let _ = io.println_error("cd: HOME not set");
+                return FAILURE;
             }
-            Err(_) => io.println_error("cd: $HOME not set"),
-        }
+        },
+        Some("-") => match shell.variables.get("OLDPWD") {
+            Some(previous) => (previous.clone(), true),
+            None => {
+                let _ = io.println_error("cd: OLDPWD not set");
+                return FAILURE;
+            }
+        },
+        Some(path) => (path.to_string(), false),
+    };
+
+    let previous = env::current_dir().ok();
+
+    if let Err(error) = env::set_current_dir(&path) {
+        let _ = io.println_error(format!("cd: {}: {}", path, describe_error(&error)).as_str());
+        return FAILURE;
     }
 
-    if let Some(path_buf) = absolute_path {
-        if let Err(_) = env::set_current_dir(path_buf) {
-            io.println_error(format!("cd: {}: No such file or directory", path).as_str());
+    let previous = match shell.variables.get("PWD") {
+        Some(pwd) => Some(pwd.clone()),
+        None => previous.map(|path| path.to_string_lossy().into_owned()),
+    };
+
+    if let Some(previous) = previous {
+        let _ = shell.variables.set("OLDPWD", previous);
+    }
+
+    if let Ok(current) = env::current_dir() {
+        let current = current.to_string_lossy().into_owned();
+
+        let announced = match announce {
+            true => io.println(&current),
+            false => Ok(()),
+        };
+
+        let _ = shell.variables.set("PWD", current);
+
+        if let Err(error) = announced {
+            return write_error(io, "cd", &error);
         }
     }
 
-    None
+    SUCCESS
 }
 
-fn print_history(start: usize, shell: &Shell, io: &mut RedirectStreams) {
+fn print_history(start: usize, shell: &Shell, io: &mut RedirectStreams) -> io::Result<()> {
     for (index, command) in shell.history.iter().skip(start).enumerate() {
-        io.println(format!("{:5}  {}", index + 1, command).as_str());
+        io.println(format!("{:5}  {}", index + 1, command).as_str())?;
     }
+
+    Ok(())
 }
 
 pub fn builtin_history(
     shell: &mut Shell,
-    arguments: &Vec<String>,
+    arguments: &[String],
     io: &mut RedirectStreams,
-) -> Option<i32> {
+) -> BuiltinResult {
     let first = if arguments.len() > 1 {
         Some(&arguments[1])
     } else {
         None
     };
 
-    match first {
-        Some(arg) if arg == "-r" => shell.read_history(&arguments[2]),
-        Some(arg) if arg == "-w" => shell.write_history(&arguments[2]),
-        Some(arg) if arg == "-a" => shell.append_history(&arguments[2]),
+    let start = match first {
+        Some(arg) if arg == "-r" => {
+            shell.read_history(&arguments[2]);
+            return SUCCESS;
+        }
+        Some(arg) if arg == "-w" => {
+            shell.write_history(&arguments[2]);
+            return SUCCESS;
+        }
+        Some(arg) if arg == "-a" => {
+            shell.append_history(&arguments[2]);
+            return SUCCESS;
+        }
         Some(value) if value.chars().all(char::is_numeric) => {
-            let start = shell.history.len() - value.parse::<usize>().unwrap();
-            print_history(start, shell, io);
+            shell.history.len() - value.parse::<usize>().unwrap()
+        }
+        None => 0,
+        _ => {
+            let _ = io.println_error("history: invalid parameter");
+            return FAILURE;
+        }
+    };
+
+    match print_history(start, shell, io) {
+        Ok(()) => SUCCESS,
+        Err(error) => write_error(io, "history", &error),
+    }
+}
+
+fn split_assignment(argument: &str) -> (&str, Option<String>) {
+    match argument.split_once('=') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (argument, None),
+    }
+}
+
+pub fn builtin_export(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let mut unexport = false;
+    let mut names: Vec<&String> = Vec::new();
+
+    for argument in arguments.iter().skip(1) {
+        match argument.as_str() {
+            "-n" => unexport = true,
+            "-p" => {}
+            _ => names.push(argument),
+        }
+    }
+
+    if names.is_empty() {
+        for (name, variable) in shell.variables.iter() {
+            if !variable.exported {
+                continue;
+            }
+
+            let line = format!("declare -x {}=\"{}\"", name, variable.value);
+            if let Err(error) = io.println(line.as_str()) {
+                return write_error(io, "export", &error);
+            }
+        }
+
+        return SUCCESS;
+    }
+
+    let mut result = SUCCESS;
+    for argument in names {
+        let (name, value) = split_assignment(argument);
+
+        if !is_valid_name(name) {
+            let _ = io
+                .println_error(format!("export: `{}': not a valid identifier", argument).as_str());
+            result = FAILURE;
+            continue;
+        }
+
+        if unexport {
+            shell.variables.unexport(name);
+        } else if let Err(message) = shell.variables.export(name, value) {
+            let _ = io.println_error(format!("export: {}", message).as_str());
+            result = FAILURE;
+        }
+    }
+
+    result
+}
+
+pub fn builtin_readonly(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let names: Vec<&String> = arguments
+        .iter()
+        .skip(1)
+        .filter(|argument| argument.as_str() != "-p")
+        .collect();
+
+    if names.is_empty() {
+        for (name, variable) in shell.variables.iter() {
+            if !variable.readonly {
+                continue;
+            }
+
+            let line = format!("declare -r {}=\"{}\"", name, variable.value);
+            if let Err(error) = io.println(line.as_str()) {
+                return write_error(io, "readonly", &error);
+            }
+        }
+
+        return SUCCESS;
+    }
+
+    let mut result = SUCCESS;
+    for argument in names {
+        let (name, value) = split_assignment(argument);
+
+        if !is_valid_name(name) {
+            let _ = io.println_error(
+                format!("readonly: `{}': not a valid identifier", argument).as_str(),
+            );
+            result = FAILURE;
+            continue;
+        }
+
+        if let Err(message) = shell.variables.mark_readonly(name, value) {
+            let _ = io.println_error(format!("readonly: {}", message).as_str());
+            result = FAILURE;
+        }
+    }
+
+    result
+}
+
+pub fn builtin_unset(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let mut result = SUCCESS;
+    let mut functions = false;
+
+    for name in arguments.iter().skip(1) {
+        match name.as_str() {
+            "-v" => continue,
+            "-f" => {
+                functions = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        if functions {
+            shell.functions.remove(name);
+        } else if let Err(message) = shell.variables.unset(name) {
+            let _ = io.println_error(format!("unset: {}", message).as_str());
+            result = FAILURE;
+        }
+    }
+
+    result
+}
+
+pub fn builtin_shopt(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let mut change: Option<bool> = None;
+    let mut quiet = false;
+    let mut reusable = false;
+    let mut names: Vec<&String> = Vec::new();
+
+    for argument in arguments.iter().skip(1) {
+        match argument.as_str() {
+            "-s" => change = Some(true),
+            "-u" => change = Some(false),
+            "-q" => quiet = true,
+            "-p" => reusable = true,
+            _ => names.push(argument),
+        }
+    }
+
+    for name in &names {
+        if !shell.options.contains_key(name.as_str()) {
+            let _ =
+                io.println_error(format!("shopt: {}: invalid shell option name", name).as_str());
+            return FAILURE;
+        }
+    }
+
+    if let (Some(enabled), false) = (change, names.is_empty()) {
+        for name in names {
+            if let Some(value) = shell.options.get_mut(name.as_str()) {
+                *value = enabled;
+            }
+        }
+
+        return SUCCESS;
+    }
+
+    let mut result = SUCCESS;
+    for (name, enabled) in shell.options.iter() {
+        if !names.is_empty() && !names.iter().any(|wanted| wanted.as_str() == *name) {
+            continue;
+        }
+
+        if change.is_some_and(|wanted| wanted != *enabled) {
+            continue;
+        }
+
+        if !enabled {
+            result = FAILURE;
+        }
+
+        if quiet {
+            continue;
+        }
+
+        let line = if reusable {
+            let flag = if *enabled { "-s" } else { "-u" };
+            format!("shopt {} {}", flag, name)
+        } else {
+            let state = if *enabled { "on" } else { "off" };
+            format!("{:<15}\t{}", name, state)
+        };
+
+        if let Err(error) = io.println(line.as_str()) {
+            return write_error(io, "shopt", &error);
+        }
+    }
+
+    if names.is_empty() && !quiet {
+        return SUCCESS;
+    }
+
+    result
+}
+
+fn jump(
+    shell: &mut Shell,
+    name: &str,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+    flow: fn(usize) -> Flow,
+) -> BuiltinResult {
+    if shell.loop_depth == 0 {
+        let _ = io.println_error(
+            format!(
+                "{}: only meaningful in a `for', `while', or `until' loop",
+                name
+            )
+            .as_str(),
+        );
+        return SUCCESS;
+    }
+
+    let (count, result) = match arguments.get(1) {
+        None => (1, SUCCESS),
+        Some(value) => match value.parse::<i64>() {
+            Ok(count) if count > 0 => (count as usize, SUCCESS),
+            Ok(_) => {
+                let _ = io.println_error(
+                    format!("{}: {}: loop count out of range", name, value).as_str(),
+                );
+                (1, FAILURE)
+            }
+            Err(_) => {
+                let _ = io.println_error(
+                    format!("{}: {}: numeric argument required", name, value).as_str(),
+                );
+                return FAILURE;
+            }
+        },
+    };
+
+    shell.flow = Some(flow(count.min(shell.loop_depth)));
+    result
+}
+
+pub fn builtin_break(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    jump(shell, "break", arguments, io, Flow::Break)
+}
+
+pub fn builtin_continue(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    jump(shell, "continue", arguments, io, Flow::Continue)
+}
+
+pub fn builtin_local(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    if shell.function_depth == 0 {
+        let _ = io.println_error("local: can only be used in a function");
+        return FAILURE;
+    }
+
+    let mut result = SUCCESS;
+    for argument in arguments.iter().skip(1) {
+        let (name, value) = split_assignment(argument);
+
+        if !is_valid_name(name) {
+            let _ =
+                io.println_error(format!("local: `{}': not a valid identifier", argument).as_str());
+            result = FAILURE;
+            continue;
+        }
+
+        if let Err(message) = shell.variables.make_local(name, value) {
+            let _ = io.println_error(format!("local: {}", message).as_str());
+            result = FAILURE;
+        }
+    }
+
+    result
+}
+
+pub fn builtin_return(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    if shell.function_depth == 0 {
+        let _ = io.println_error("return: can only `return' from a function or sourced script");
+        return BuiltinResult::Status(2);
+    }
+
+    let code = match arguments.get(1) {
+        None => shell.last_status,
+        Some(value) => match value.parse::<i32>() {
+            Ok(code) => code & 0xff,
+            Err(_) => {
+                let _ = io.println_error(
+                    format!("return: {}: numeric argument required", value).as_str(),
+                );
+                2
+            }
+        },
+    };
+
+    shell.flow = Some(Flow::Return);
+    BuiltinResult::Status(code)
+}
+
+pub fn builtin_wait(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    if arguments.len() < 2 {
+        let pids: Vec<i32> = shell.jobs.iter().map(|job| job.pid).collect();
+
+        for pid in pids {
+            wait_for(pid);
+            shell.jobs.remove(pid);
+        }
+
+        shell.jobs.clear_finished();
+        return SUCCESS;
+    }
+
+    let mut status = 0;
+    for argument in arguments.iter().skip(1) {
+        let pid = match argument.strip_prefix('%') {
+            Some(_) => match shell.jobs.find(argument) {
+                Some(job) => job.pid,
+                None => {
+                    let _ = io.println_error(format!("wait: {}: no such job", argument).as_str());
+                    status = STATUS_NOT_FOUND;
+                    continue;
+                }
+            },
+            None => match argument.parse::<i32>() {
+                Ok(pid) => pid,
+                Err(_) => {
+                    let _ = io.println_error(
+                        format!("wait: `{}': not a pid or valid job spec", argument).as_str(),
+                    );
+                    status = 2;
+                    continue;
+                }
+            },
+        };
+
+        if shell.jobs.remove(pid).is_some() {
+            status = wait_for(pid);
+            continue;
+        }
+
+        match shell.jobs.take_finished(pid) {
+            Some(code) => status = code,
+            None => {
+                let _ = io.println_error(
+                    format!("wait: pid {} is not a child of this shell", argument).as_str(),
+                );
+                status = STATUS_NOT_FOUND;
+            }
+        }
+    }
+
+    BuiltinResult::Status(status)
+}
+
+pub fn builtin_jobs(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let mut long = false;
+    let mut pids_only = false;
+
+    for argument in arguments.iter().skip(1) {
+        match argument.as_str() {
+            "-l" => long = true,
+            "-p" => pids_only = true,
+            _ => {
+                let _ = io.println_error(format!("jobs: {}: invalid option", argument).as_str());
+                return BuiltinResult::Status(2);
+            }
+        }
+    }
+
+    for (job, marker, description) in shell.jobs.update() {
+        if shell.jobs.get_by_pid(job.pid).is_some() {
+            continue;
+        }
+
+        if let Err(error) = io.println(format_job(&job, marker, &description).as_str()) {
+            return write_error(io, "jobs", &error);
+        }
+    }
+
+    for job in shell.jobs.iter() {
+        if pids_only {
+            if let Err(error) = io.println(job.pid.to_string().as_str()) {
+                return write_error(io, "jobs", &error);
+            }
+            continue;
+        }
+
+        let marker = shell.jobs.marker(job.id);
+        let mut line = format_job(job, marker, &job.describe_state());
+        if long {
+            let (head, tail) = line.split_at(line.find(' ').unwrap_or(line.len()));
+            line = format!("{} {}{}", head, job.pid, &tail[1..]);
+        }
+        if job.state == JobState::Running {
+            line.push_str(" &");
+        }
+
+        if let Err(error) = io.println(line.as_str()) {
+            return write_error(io, "jobs", &error);
+        }
+    }
+
+    SUCCESS
+}
+
+/// Resolves the job `fg` or `bg` is about, the current one when no spec is given.
+fn resume_target(
+    shell: &mut Shell,
+    name: &str,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> Option<Job> {
+    if shell.terminal.is_none() {
+        let _ = io.println_error(format!("{}: no job control", name).as_str());
+        return None;
+    }
+
+    shell.jobs.update();
+
+    let spec = arguments.get(1).map(String::as_str);
+    match shell.jobs.find(spec.unwrap_or("%%")) {
+        Some(job) => Some(job.clone()),
+        None => {
+            let spec = spec.unwrap_or("current");
+            let _ = io.println_error(format!("{}: {}: no such job", name, spec).as_str());
+            None
+        }
+    }
+}
+
+pub fn builtin_fg(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let Some(job) = resume_target(shell, "fg", arguments, io) else {
+        return FAILURE;
+    };
+
+    if let Err(error) = io.println(job.command.as_str()) {
+        return write_error(io, "fg", &error);
+    }
+
+    shell.jobs.set_state(job.pid, JobState::Running);
+    if let Some(terminal) = &shell.terminal {
+        terminal.give_to(job.pid);
+    }
+
+    unsafe { libc::kill(-job.pid, libc::SIGCONT) };
+
+    BuiltinResult::Status(wait_foreground(shell, job.pid, &job.command))
+}
+
+pub fn builtin_bg(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let Some(job) = resume_target(shell, "bg", arguments, io) else {
+        return FAILURE;
+    };
+
+    if job.state == JobState::Running {
+        let _ = io.println_error(format!("bg: job {} already in background", job.id).as_str());
+        return SUCCESS;
+    }
+
+    shell.jobs.set_state(job.pid, JobState::Running);
+    unsafe { libc::kill(-job.pid, libc::SIGCONT) };
+
+    match io.println(format!("[{}]+ {} &", job.id, job.command).as_str()) {
+        Ok(()) => SUCCESS,
+        Err(error) => write_error(io, "bg", &error),
+    }
+}
+
+pub fn builtin_trap(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let mut arguments = &arguments[1..];
+
+    let print = match arguments.first().map(String::as_str) {
+        Some("-l") => {
+            let names: Vec<String> = signal_names()
+                .map(|(signal, name)| format!("{:2}) SIG{}", signal, name))
+                .collect();
+
+            for row in names.chunks(5) {
+                if let Err(error) = io.println(row.join("\t").as_str()) {
+                    return write_error(io, "trap", &error);
+                }
+            }
+
+            return SUCCESS;
+        }
+        Some("-p") => {
+            arguments = &arguments[1..];
+            true
+        }
+        Some("--") => {
+            arguments = &arguments[1..];
+            false
+        }
+        _ => false,
+    };
+
+    let mut status = SUCCESS;
+    let mut conditions = Vec::new();
+
+    let (action, specs) = match arguments.first() {
+        _ if print || arguments.is_empty() => (None, arguments),
+        Some(first) if arguments.len() == 1 && TrapCondition::parse(first).is_some() => {
+            (None, arguments)
+        }
+        Some(first) if first == "-" => (None, &arguments[1..]),
+        Some(first) => (Some(first), &arguments[1..]),
+        None => (None, arguments),
+    };
+
+    for spec in specs {
+        match TrapCondition::parse(spec) {
+            Some(condition) => conditions.push(condition),
+            None => {
+                let _ = io.println_error(
+                    format!("trap: {}: invalid signal specification", spec).as_str(),
+                );
+                status = FAILURE;
+            }
+        }
+    }
+
+    if print || arguments.is_empty() {
+        for (condition, action) in shell.traps.iter() {
+            if !conditions.is_empty() && !conditions.contains(condition) {
+                continue;
+            }
+
+            let line = format!("trap -- {} {}", quote_word(action), condition);
+            if let Err(error) = io.println(line.as_str()) {
+                return write_error(io, "trap", &error);
+            }
+        }
+
+        return status;
+    }
+
+    for condition in conditions {
+        match action {
+            Some(action) => shell.traps.set(condition, action.clone()),
+            None => shell.traps.reset(condition, shell.interactive),
+        }
+    }
+
+    status
+}
+
+pub fn builtin_set(
+    shell: &mut Shell,
+    arguments: &[String],
+    io: &mut RedirectStreams,
+) -> BuiltinResult {
+    let mut arguments = arguments.iter().skip(1);
+
+    while let Some(argument) = arguments.next() {
+        let enabled = match argument.as_str() {
+            "--" => {
+                shell.positional_parameters = arguments.cloned().collect();
+                return SUCCESS;
+            }
+            "-o" => true,
+            "+o" => false,
+            _ => {
+                let _ = io.println_error(format!("set: {}: invalid option", argument).as_str());
+                return BuiltinResult::Status(2);
+            }
+        };
+
+        let Some(name) = arguments.next() else {
+            for (name, value) in shell.set_options.iter() {
+                let line = match enabled {
+                    true => {
+                        let state = if *value { "on" } else { "off" };
+                        format!("{:<15}\t{}", name, state)
+                    }
+                    false => {
+                        let flag = if *value { '-' } else { '+' };
+                        format!("set {}o {}", flag, name)
+                    }
+                };
+
+                if let Err(error) = io.println(line.as_str()) {
+                    return write_error(io, "set", &error);
+                }
+            }
+
+            return SUCCESS;
+        };
+
+        match shell.set_options.get_mut(name.as_str()) {
+            Some(value) => *value = enabled,
+            None => {
+                let _ = io.println_error(format!("set: {}: invalid option name", name).as_str());
+                return FAILURE;
+            }
         }
-        None => print_history(0, shell, io),
-        _ => io.println_error("history: invalid parameter"),
     }
 
-    None
+    SUCCESS
 }
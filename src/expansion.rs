@@ -0,0 +1,1140 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::{
+    evaluate_arithmetic, expand_braces, expand_pathname, has_wildcards, home_directory,
+    is_valid_name, pattern_matches, run_substitution, substitution_end, Assignment, PatternChar,
+    Redirect, RedirectTarget, Shell, SimpleCommand,
+};
+
+const SINGLE: char = '\'';
+const DOUBLE: char = '"';
+const BACKSLASH: char = '\\';
+const DOLLAR: char = '$';
+const NEWLINE: char = '\n';
+const OPEN_BRACE: char = '{';
+const CLOSE_BRACE: char = '}';
+const BACKTICK: char = '`';
+const OPEN_PARENTHESIS: char = '(';
+const CLOSE_PARENTHESIS: char = ')';
+
+const TILDE: char = '~';
+const SLASH: char = '/';
+const COLON: char = ':';
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/// An expanded field, `quoted` ones are kept even when they end up empty.
+struct Field {
+    characters: Vec<PatternChar>,
+    quoted: bool,
+}
+
+struct WordExpander<'a> {
+    shell: &'a mut Shell,
+    chars: Peekable<Chars<'a>>,
+    fields: Vec<Field>,
+    builder: Vec<PatternChar>,
+    quoted: bool,
+    split: bool,
+    assignment: bool,
+    delimited: bool,
+    vanished: bool,
+}
+
+impl<'a> WordExpander<'a> {
+    fn new(shell: &'a mut Shell, word: &'a str, split: bool) -> WordExpander<'a> {
+        WordExpander {
+            shell,
+            chars: word.chars().peekable(),
+            fields: Vec::new(),
+            builder: Vec::new(),
+            quoted: false,
+            split,
+            assignment: false,
+            delimited: false,
+            vanished: false,
+        }
+    }
+
+    fn push(&mut self, character: char, quoted: bool) {
+        self.builder.push((character, quoted));
+        self.delimited = false;
+    }
+
+    fn push_str(&mut self, value: &str, quoted: bool) {
+        for character in value.chars() {
+            self.push(character, quoted);
+        }
+    }
+
+    fn finish_field(&mut self) {
+        if self.builder.is_empty() && !self.quoted {
+            return;
+        }
+
+        self.fields.push(Field {
+            characters: std::mem::take(&mut self.builder),
+            quoted: std::mem::take(&mut self.quoted),
+        });
+    }
+
+    fn separators(&self) -> String {
+        self.shell
+            .get_parameter("IFS")
+            .unwrap_or_else(|| DEFAULT_IFS.to_string())
+    }
+
+    fn push_fields(&mut self, value: &str, in_quote: bool) {
+        if in_quote || !self.split {
+            self.push_str(value, in_quote);
+            return;
+        }
+
+        self.push_split(value.chars().map(|character| (character, false)));
+    }
+
+    /// Pushes expansion results, splitting the unquoted characters on `IFS`.
+    ///
+    /// Runs of `IFS` whitespace delimit a single field and are ignored at the edges, while every
+    /// other `IFS` character delimits one, even if that leaves an empty field behind.
+    fn push_split(&mut self, characters: impl IntoIterator<Item = PatternChar>) {
+        let separators = self.separators();
+
+        for (character, quoted) in characters {
+            if quoted || !separators.contains(character) {
+                self.push(character, quoted);
+            } else if DEFAULT_IFS.contains(character) {
+                if !self.builder.is_empty() || self.quoted {
+                    self.finish_field();
+                    self.delimited = true;
+                }
+            } else if self.delimited {
+                self.delimited = false;
+            } else {
+                self.quoted = true;
+                self.finish_field();
+            }
+        }
+    }
+
+    /// Pushes `$@`, `$*` or all the elements of an array, where a quoted `"$@"` or `"${name[@]}"`
+    /// keeps every element as its own field and `*` joins them with the first `IFS` character.
+    fn push_elements(&mut self, parameters: Vec<String>, star: bool, in_quote: bool) {
+        if !self.split || (star && in_quote) {
+            let separator = match star {
+                true => self.separators().chars().next().map(String::from),
+                false => Some(String::from(' ')),
+            };
+
+            let joined = parameters.join(separator.as_deref().unwrap_or(""));
+            self.push_str(&joined, in_quote);
+            return;
+        }
+
+        if parameters.is_empty() && in_quote {
+            self.vanished = true;
+        }
+
+        for (index, parameter) in parameters.iter().enumerate() {
+            if index != 0 {
+                if in_quote {
+                    self.finish_field();
+                    self.quoted = true;
+                } else if !self.builder.is_empty() {
+                    self.finish_field();
+                }
+            }
+
+            self.push_fields(parameter, in_quote);
+        }
+    }
+
+    fn expand(mut self) -> ExpansionResult<Vec<Field>> {
+        self.tilde();
+
+        while let Some(character) = self.chars.next() {
+            match character {
+                SINGLE => {
+                    self.quoted = true;
+
+                    for character in self.chars.by_ref() {
+                        if character == SINGLE {
+                            break;
+                        }
+
+                        self.builder.push((character, true));
+                    }
+                }
+                DOUBLE => {
+                    let (quoted, length) = (self.quoted, self.builder.len());
+                    self.quoted = true;
+
+                    while let Some(character) = self.chars.next() {
+                        match character {
+                            DOUBLE => break,
+                            BACKSLASH => self.backslash(true),
+                            DOLLAR => self.dollar(true)?,
+                            BACKTICK => self.backtick(true),
+                            _ => self.push(character, true),
+                        }
+                    }
+
+                    if std::mem::take(&mut self.vanished) && self.builder.len() == length {
+                        self.quoted = quoted;
+                    }
+                }
+                BACKSLASH => {
+                    self.quoted = true;
+                    self.backslash(false);
+                }
+                DOLLAR => self.dollar(false)?,
+                BACKTICK => self.backtick(false),
+                COLON if self.assignment => {
+                    self.push(character, false);
+                    self.tilde();
+                }
+                _ => self.push(character, false),
+            }
+        }
+
+        self.fields.push(Field {
+            characters: self.builder,
+            quoted: self.quoted,
+        });
+
+        Ok(self.fields)
+    }
+
+    fn expand_here_document(mut self) -> ExpansionResult<String> {
+        while let Some(character) = self.chars.next() {
+            match character {
+                BACKSLASH => match self.chars.next() {
+                    Some(NEWLINE) => {}
+                    Some(escaped @ (BACKSLASH | DOLLAR | BACKTICK)) => self.push(escaped, true),
+                    Some(escaped) => {
+                        self.push(BACKSLASH, true);
+                        self.push(escaped, true);
+                    }
+                    None => self.push(BACKSLASH, true),
+                },
+                DOLLAR => self.dollar(true)?,
+                BACKTICK => self.backtick(true),
+                _ => self.push(character, true),
+            }
+        }
+
+        Ok(to_text(&self.builder))
+    }
+
+    /// Replaces an unquoted `~prefix` at the current position with the directory it names.
+    fn tilde(&mut self) {
+        let mut lookahead = self.chars.clone();
+        if lookahead.next() != Some(TILDE) {
+            return;
+        }
+
+        let mut prefix = String::new();
+        for character in lookahead {
+            if character == SLASH || (self.assignment && character == COLON) {
+                break;
+            }
+
+            if matches!(character, SINGLE | DOUBLE | BACKSLASH | DOLLAR | BACKTICK) {
+                return;
+            }
+
+            prefix.push(character);
+        }
+
+        let directory = match prefix.as_str() {
+            "" => self
+                .shell
+                .variables
+                .get("HOME")
+                .cloned()
+                .or_else(|| home_directory(None)),
+            "+" => self.shell.variables.get("PWD").cloned(),
+            "-" => self.shell.variables.get("OLDPWD").cloned(),
+            user => home_directory(Some(user)),
+        };
+
+        if let Some(directory) = directory {
+            self.chars.nth(prefix.chars().count());
+            self.push_str(&directory, true);
+        }
+    }
+
+    fn backslash(&mut self, in_quote: bool) {
+        if let Some(character) = self.chars.next() {
+            if in_quote && !WordExpander::is_escapable_in_quote(character) {
+                self.push(BACKSLASH, true);
+            }
+
+            self.push(character, true);
+        }
+    }
+
+    fn is_escapable_in_quote(character: char) -> bool {
+        matches!(character, BACKSLASH | DOUBLE | DOLLAR | BACKTICK)
+    }
+
+    fn backtick(&mut self, in_quote: bool) {
+        let mut source = String::new();
+
+        while let Some(character) = self.chars.next() {
+            match character {
+                BACKTICK => break,
+                BACKSLASH => match self.chars.next() {
+                    Some(escaped @ (BACKSLASH | DOLLAR | BACKTICK)) => source.push(escaped),
+                    Some(escaped) => {
+                        source.push(BACKSLASH);
+                        source.push(escaped);
+                    }
+                    None => source.push(BACKSLASH),
+                },
+                _ => source.push(character),
+            }
+        }
+
+        let output = run_substitution(self.shell, &source);
+        self.push_fields(&output, in_quote);
+    }
+
+    /// Takes the source of a `$(...)` command substitution, up to the `)` the lexer found closing it.
+    fn take_substitution(&mut self) -> String {
+        let rest: String = self.chars.clone().collect();
+        let end = substitution_end(&rest).unwrap_or(rest.len());
+
+        let source = rest[..end].to_string();
+        for _ in rest[..end].chars() {
+            self.chars.next();
+        }
+        self.chars.next_if_eq(&CLOSE_PARENTHESIS);
+
+        source
+    }
+
+    fn take_balanced(&mut self, open: char, close: char) -> String {
+        let mut source = String::new();
+        let mut depth = 1;
+
+        while let Some(character) = self.chars.next() {
+            match character {
+                _ if character == open => depth += 1,
+                _ if character == close => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                SINGLE | DOUBLE | BACKTICK => {
+                    source.push(character);
+
+                    while let Some(inner) = self.chars.next() {
+                        source.push(inner);
+
+                        if inner == character {
+                            break;
+                        }
+
+                        if inner == BACKSLASH && character != SINGLE {
+                            source.extend(self.chars.next());
+                        }
+                    }
+
+                    continue;
+                }
+                BACKSLASH => {
+                    source.push(character);
+                    source.extend(self.chars.next());
+                    continue;
+                }
+                _ => {}
+            }
+
+            source.push(character);
+        }
+
+        source
+    }
+
+    fn dollar(&mut self, in_quote: bool) -> ExpansionResult<()> {
+        let name = match self.chars.peek() {
+            Some(&OPEN_PARENTHESIS) => {
+                self.chars.next();
+
+                let source = match self.chars.peek() {
+                    Some(&OPEN_PARENTHESIS) => {
+                        self.take_balanced(OPEN_PARENTHESIS, CLOSE_PARENTHESIS)
+                    }
+                    _ => self.take_substitution(),
+                };
+
+                let output = match arithmetic_expression(&source) {
+                    Some(expression) => self.arithmetic(expression)?,
+                    None => run_substitution(self.shell, &source),
+                };
+
+                self.push_fields(&output, in_quote);
+                return Ok(());
+            }
+            Some(&OPEN_BRACE) => {
+                self.chars.next();
+
+                let body = self.take_balanced(OPEN_BRACE, CLOSE_BRACE);
+                return self.parameter(&body, in_quote);
+            }
+            Some(&character) if is_special_parameter(character) => {
+                self.chars.next();
+                character.to_string()
+            }
+            Some(&character) if is_name_start(character) => {
+                let mut name = String::new();
+                while let Some(character) = self.chars.next_if(|&c| is_name_part(c)) {
+                    name.push(character);
+                }
+
+                name
+            }
+            _ => {
+                self.push(DOLLAR, in_quote);
+                return Ok(());
+            }
+        };
+
+        if name == "@" || name == "*" {
+            let parameters = self.shell.get_array(&name);
+            self.push_elements(parameters, name == "*", in_quote);
+        } else if let Some(value) = self.shell.get_parameter(&name) {
+            self.push_fields(&value, in_quote);
+        }
+
+        Ok(())
+    }
+
+    fn arithmetic(&mut self, expression: &str) -> ExpansionResult<String> {
+        self.evaluate(expression).map(|value| value.to_string())
+    }
+
+    fn evaluate(&mut self, expression: &str) -> ExpansionResult<i64> {
+        let expression = expand_word(self.shell, expression)?;
+
+        evaluate_arithmetic(self.shell, &expression)
+            .map_err(|error| format!("{}: {}", expression.trim(), error))
+    }
+
+    fn push_word(&mut self, word: &str, in_quote: bool) -> ExpansionResult<()> {
+        for field in WordExpander::new(self.shell, word, false).expand()? {
+            self.quoted |= field.quoted;
+
+            if in_quote || !self.split {
+                for (character, quoted) in field.characters {
+                    self.push(character, quoted || in_quote);
+                }
+            } else {
+                self.push_split(field.characters);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up `name[subscript]`: every element for `@` or `*`, otherwise the one at an arithmetic index.
+    fn elements(&mut self, name: &str, subscript: &str) -> ExpansionResult<Vec<String>> {
+        let mut elements = self.shell.get_array(name);
+        if subscript == "@" || subscript == "*" {
+            return Ok(elements);
+        }
+
+        let mut index = self.evaluate(subscript)?;
+        if index < 0 {
+            index += elements.len() as i64;
+        }
+
+        if index < 0 || index >= elements.len() as i64 {
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![elements.swap_remove(index as usize)])
+    }
+
+    /// Expands the inside of `${...}`, with its operators applied to the named parameter.
+    fn parameter(&mut self, body: &str, in_quote: bool) -> ExpansionResult<()> {
+        let bad_substitution = || format!("${{{}}}: bad substitution", body);
+
+        if let Some(name) = body.strip_prefix('#') {
+            if !name.is_empty() {
+                let (name, rest) = name.split_at(parameter_name_length(name));
+
+                let count = match split_subscript(rest) {
+                    _ if name.is_empty() => return Err(bad_substitution()),
+                    Some((subscript @ ("@" | "*"), "")) => self.elements(name, subscript)?.len(),
+                    Some((subscript, "")) => {
+                        self.elements(name, subscript)?.concat().chars().count()
+                    }
+                    None if rest.is_empty() && (name == "@" || name == "*") => {
+                        self.shell.get_array(name).len()
+                    }
+                    None if rest.is_empty() => {
+                        let value = self.shell.get_parameter(name).unwrap_or_default();
+                        value.chars().count()
+                    }
+                    _ => return Err(bad_substitution()),
+                };
+
+                self.push_fields(&count.to_string(), in_quote);
+                return Ok(());
+            }
+        }
+
+        let length = parameter_name_length(body);
+        if length == 0 {
+            return Err(bad_substitution());
+        }
+
+        let (name, rest) = body.split_at(length);
+        let (subscript, rest) = match split_subscript(rest) {
+            Some((subscript, rest)) => (Some(subscript), rest),
+            None => (None, rest),
+        };
+
+        let value = match subscript {
+            Some(subscript) => {
+                let elements = self.elements(name, subscript)?;
+                Some(elements.join(" ")).filter(|_| !elements.is_empty())
+            }
+            None => self.shell.get_parameter(name),
+        };
+
+        let (colon, rest) = match rest.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+
+        let Some(operator) = rest.chars().next() else {
+            if colon {
+                return Err(bad_substitution());
+            }
+
+            if let Some(subscript @ ("@" | "*")) = subscript {
+                let elements = self.elements(name, subscript)?;
+                self.push_elements(elements, subscript == "*", in_quote);
+            } else if name == "@" || name == "*" {
+                let parameters = self.shell.get_array(name);
+                self.push_elements(parameters, name == "*", in_quote);
+            } else {
+                self.push_fields(&value.unwrap_or_default(), in_quote);
+            }
+
+            return Ok(());
+        };
+
+        let word = &rest[operator.len_utf8()..];
+        let is_set = match &value {
+            Some(value) => !colon || !value.is_empty(),
+            None => false,
+        };
+
+        match operator {
+            '-' | '=' | '?' | '+' => {}
+            _ if colon => {
+                let selector = match subscript {
+                    Some(subscript @ ("@" | "*")) => Some(subscript),
+                    None if name == "@" || name == "*" => Some(name),
+                    _ => None,
+                };
+
+                if let Some(selector) = selector {
+                    let elements = match subscript {
+                        Some(subscript) => self.elements(name, subscript)?,
+                        None => {
+                            let mut parameters = self
+                                .shell
+                                .get_parameter("0")
+                                .into_iter()
+                                .collect::<Vec<_>>();
+                            parameters.extend(self.shell.get_array(name));
+                            parameters
+                        }
+                    };
+
+                    let elements = self.slice_elements(elements, rest)?;
+                    self.push_elements(elements, selector == "*", in_quote);
+                    return Ok(());
+                }
+
+                let value = value.unwrap_or_default();
+                let substring = self.substring(name, &value, rest)?;
+
+                self.push_fields(&substring, in_quote);
+                return Ok(());
+            }
+            '#' | '%' => {
+                let longest = word.starts_with(operator);
+                let word = if longest { &word[1..] } else { word };
+
+                let pattern = expand_pattern(self.shell, word)?;
+                let value = value.unwrap_or_default();
+
+                let trimmed = if operator == '#' {
+                    remove_prefix(&value, &pattern, longest)
+                } else {
+                    remove_suffix(&value, &pattern, longest)
+                };
+
+                self.push_fields(&trimmed, in_quote);
+                return Ok(());
+            }
+            '/' => {
+                let (mode, word) = match word.chars().next() {
+                    Some('/') => (Replace::All, &word[1..]),
+                    Some('#') => (Replace::Prefix, &word[1..]),
+                    Some('%') => (Replace::Suffix, &word[1..]),
+                    _ => (Replace::First, word),
+                };
+
+                let (pattern, replacement) = split_unquoted(word, '/');
+                let pattern = expand_pattern(self.shell, pattern)?;
+                let replacement = match replacement {
+                    Some(replacement) => expand_word(self.shell, replacement)?,
+                    None => String::new(),
+                };
+
+                let value = value.unwrap_or_default();
+                let replaced = replace_pattern(&value, &pattern, &replacement, mode);
+
+                self.push_fields(&replaced, in_quote);
+                return Ok(());
+            }
+            _ => return Err(bad_substitution()),
+        }
+
+        match (operator, is_set) {
+            ('+', true) => self.push_word(word, in_quote)?,
+            ('+', false) => {}
+            (_, true) => self.push_fields(&value.unwrap_or_default(), in_quote),
+            ('-', false) => self.push_word(word, in_quote)?,
+            ('=', false) => {
+                if !is_valid_name(name) {
+                    return Err(format!("${}: cannot assign in this way", name));
+                }
+
+                let value = expand_word(self.shell, word)?;
+                self.shell.variables.set(name, value.clone())?;
+                self.push_fields(&value, in_quote);
+            }
+            (_, false) => {
+                let message = match word {
+                    "" => "parameter null or not set".to_string(),
+                    _ => expand_word(self.shell, word)?,
+                };
+
+                self.shell.parameter_error = true;
+                return Err(format!("{}: {}", name, message));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Slices `${@:offset:length}` or `${name[@]:offset:length}`, where the positional
+    /// parameters come with `$0` in front so that offset 1 is `$1`.
+    fn slice_elements(
+        &mut self,
+        mut elements: Vec<String>,
+        range: &str,
+    ) -> ExpansionResult<Vec<String>> {
+        let count = elements.len() as i64;
+
+        let (offset, length) = split_unquoted(range, ':');
+
+        let mut start = self.evaluate(offset)?;
+        if start < 0 {
+            start += count;
+        }
+
+        if start < 0 || start > count {
+            return Ok(Vec::new());
+        }
+
+        let end = match length {
+            None => count,
+            Some(length) => {
+                let length = self.evaluate(length)?;
+                if length < 0 {
+                    return Err(format!("{}: substring expression < 0", length));
+                }
+
+                start.saturating_add(length).min(count)
+            }
+        };
+
+        elements.truncate(end as usize);
+        Ok(elements.split_off(start as usize))
+    }
+
+    fn substring(&mut self, name: &str, value: &str, range: &str) -> ExpansionResult<String> {
+        let characters: Vec<char> = value.chars().collect();
+        let count = characters.len() as i64;
+
+        let (offset, length) = split_unquoted(range, ':');
+
+        let mut start = self.evaluate(offset)?;
+        if start < 0 {
+            start += count;
+        }
+
+        if start < 0 || start > count {
+            return Ok(String::new());
+        }
+
+        let end = match length {
+            None => count,
+            Some(length) => {
+                let length = self.evaluate(length)?;
+
+                let end = if length < 0 {
+                    count + length
+                } else {
+                    start.saturating_add(length).min(count)
+                };
+
+                if end < start {
+                    return Err(format!("{}: {}: substring expression < 0", name, length));
+                }
+
+                end
+            }
+        };
+
+        Ok(characters[start as usize..end as usize].iter().collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Replace {
+    First,
+    All,
+    Prefix,
+    Suffix,
+}
+
+fn parameter_name_length(text: &str) -> usize {
+    match text.chars().next() {
+        Some(character) if character.is_ascii_digit() => {
+            text.chars().take_while(|c| c.is_ascii_digit()).count()
+        }
+        Some(character) if is_special_parameter(character) => 1,
+        Some(character) if is_name_start(character) => {
+            text.chars().take_while(|&c| is_name_part(c)).count()
+        }
+        _ => 0,
+    }
+}
+
+/// Splits the `[subscript]` following a parameter name from the rest of the expansion.
+fn split_subscript(text: &str) -> Option<(&str, &str)> {
+    let inner = text.strip_prefix('[')?;
+    let end = inner.find(']')?;
+
+    Some((&inner[..end], &inner[end + 1..]))
+}
+
+/// Splits at the first `separator` that is not quoted or escaped.
+fn split_unquoted(text: &str, separator: char) -> (&str, Option<&str>) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (index, character) in text.char_indices() {
+        match (character, quote) {
+            _ if escaped => escaped = false,
+            (BACKSLASH, None | Some(DOUBLE)) => escaped = true,
+            (SINGLE | DOUBLE, None) => quote = Some(character),
+            (_, Some(open)) if character == open => quote = None,
+            (_, None) if character == separator => {
+                return (&text[..index], Some(&text[index + separator.len_utf8()..]))
+            }
+            _ => {}
+        }
+    }
+
+    (text, None)
+}
+
+fn slice(characters: &[char], start: usize, end: usize) -> String {
+    characters[start..end].iter().collect()
+}
+
+fn remove_prefix(value: &str, pattern: &[PatternChar], longest: bool) -> String {
+    let characters: Vec<char> = value.chars().collect();
+    let count = characters.len();
+
+    let mut ends: Vec<usize> = (0..=count).collect();
+    if longest {
+        ends.reverse();
+    }
+
+    for end in ends {
+        if pattern_matches(pattern, &slice(&characters, 0, end)) {
+            return slice(&characters, end, count);
+        }
+    }
+
+    value.to_string()
+}
+
+fn remove_suffix(value: &str, pattern: &[PatternChar], longest: bool) -> String {
+    let characters: Vec<char> = value.chars().collect();
+    let count = characters.len();
+
+    let mut starts: Vec<usize> = (0..=count).collect();
+    if !longest {
+        starts.reverse();
+    }
+
+    for start in starts {
+        if pattern_matches(pattern, &slice(&characters, start, count)) {
+            return slice(&characters, 0, start);
+        }
+    }
+
+    value.to_string()
+}
+
+fn replace_pattern(
+    value: &str,
+    pattern: &[PatternChar],
+    replacement: &str,
+    mode: Replace,
+) -> String {
+    let characters: Vec<char> = value.chars().collect();
+    let count = characters.len();
+
+    match mode {
+        Replace::Prefix => {
+            for end in (0..=count).rev() {
+                if pattern_matches(pattern, &slice(&characters, 0, end)) {
+                    return format!("{}{}", replacement, slice(&characters, end, count));
+                }
+            }
+
+            return value.to_string();
+        }
+        Replace::Suffix => {
+            for start in 0..=count {
+                if pattern_matches(pattern, &slice(&characters, start, count)) {
+                    return format!("{}{}", slice(&characters, 0, start), replacement);
+                }
+            }
+
+            return value.to_string();
+        }
+        Replace::First | Replace::All => {}
+    }
+
+    if pattern.is_empty() {
+        return value.to_string();
+    }
+
+    let mut result = String::new();
+    let mut start = 0;
+    let mut replaced = false;
+
+    while start < count {
+        let found = if replaced && mode == Replace::First {
+            None
+        } else {
+            (start + 1..=count)
+                .rev()
+                .find(|&end| pattern_matches(pattern, &slice(&characters, start, end)))
+        };
+
+        match found {
+            Some(end) => {
+                result.push_str(replacement);
+                start = end;
+                replaced = true;
+            }
+            None => {
+                result.push(characters[start]);
+                start += 1;
+            }
+        }
+    }
+
+    result
+}
+
+fn to_text(characters: &[PatternChar]) -> String {
+    characters.iter().map(|&(character, _)| character).collect()
+}
+
+fn is_special_parameter(character: char) -> bool {
+    matches!(character, '?' | '$' | '!' | '#' | '@' | '*') || character.is_ascii_digit()
+}
+
+fn is_name_start(character: char) -> bool {
+    character == '_' || character.is_ascii_alphabetic()
+}
+
+fn is_name_part(character: char) -> bool {
+    character == '_' || character.is_ascii_alphanumeric()
+}
+
+pub type ExpansionResult<T> = Result<T, String>;
+
+/// Returns the inside of `((expression))` when a `$(...)` body is an arithmetic expansion.
+fn arithmetic_expression(source: &str) -> Option<&str> {
+    let inner = source.strip_prefix(OPEN_PARENTHESIS)?;
+
+    let mut depth = 0;
+    for (index, character) in inner.char_indices() {
+        match character {
+            OPEN_PARENTHESIS => depth += 1,
+            CLOSE_PARENTHESIS if depth > 0 => depth -= 1,
+            CLOSE_PARENTHESIS if index + 1 == inner.len() => return Some(&inner[..index]),
+            CLOSE_PARENTHESIS => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+pub fn expand_assignment(shell: &mut Shell, value: &str) -> ExpansionResult<String> {
+    let mut expander = WordExpander::new(shell, value, false);
+    expander.assignment = true;
+
+    let fields = expander.expand()?;
+
+    Ok(fields
+        .iter()
+        .map(|field| to_text(&field.characters))
+        .collect())
+}
+
+pub fn expand_word(shell: &mut Shell, word: &str) -> ExpansionResult<String> {
+    let fields = WordExpander::new(shell, word, false).expand()?;
+
+    Ok(fields
+        .iter()
+        .map(|field| to_text(&field.characters))
+        .collect())
+}
+
+/// Expands a word into a pattern, where only the characters that were not quoted are special.
+pub fn expand_pattern(shell: &mut Shell, word: &str) -> ExpansionResult<Vec<PatternChar>> {
+    let fields = WordExpander::new(shell, word, false).expand()?;
+
+    Ok(fields
+        .into_iter()
+        .flat_map(|field| field.characters)
+        .collect())
+}
+
+fn expand_pathname_or_keep(shell: &Shell, pattern: &[PatternChar]) -> ExpansionResult<Vec<String>> {
+    let text = to_text(pattern);
+
+    if !has_wildcards(pattern) {
+        return Ok(vec![text]);
+    }
+
+    let paths = expand_pathname(pattern);
+    if !paths.is_empty() || shell.option("nullglob") {
+        return Ok(paths);
+    }
+
+    if shell.option("failglob") {
+        return Err(format!("no match: {}", text));
+    }
+
+    Ok(vec![text])
+}
+
+fn expand_fields(shell: &mut Shell, word: &str) -> ExpansionResult<Vec<String>> {
+    let mut arguments = Vec::new();
+
+    for field in WordExpander::new(shell, word, true).expand()? {
+        if field.quoted || !field.characters.is_empty() {
+            arguments.extend(expand_pathname_or_keep(shell, &field.characters)?);
+        }
+    }
+
+    Ok(arguments)
+}
+
+pub fn expand_arguments(shell: &mut Shell, words: &[String]) -> ExpansionResult<Vec<String>> {
+    let mut arguments = Vec::new();
+
+    for word in words {
+        for word in expand_braces(word) {
+            arguments.extend(expand_fields(shell, &word)?);
+        }
+    }
+
+    Ok(arguments)
+}
+
+pub fn expand_simple_command(
+    shell: &mut Shell,
+    parsed_line: &SimpleCommand,
+) -> ExpansionResult<SimpleCommand> {
+    let arguments = expand_arguments(shell, &parsed_line.arguments)?;
+
+    let mut assignments = Vec::new();
+    for assignment in &parsed_line.assignments {
+        assignments.push(Assignment {
+            name: assignment.name.clone(),
+            value: expand_assignment(shell, &assignment.value)?,
+        });
+    }
+
+    Ok(SimpleCommand {
+        assignments,
+        arguments,
+        redirects: expand_redirects(shell, &parsed_line.redirects)?,
+    })
+}
+
+pub fn expand_redirects(
+    shell: &mut Shell,
+    redirects: &[Redirect],
+) -> ExpansionResult<Vec<Redirect>> {
+    let mut expanded = Vec::new();
+    for redirect in redirects {
+        expanded.push(Redirect {
+            fd: redirect.fd,
+            target: expand_redirect_target(shell, &redirect.target)?,
+        });
+    }
+
+    Ok(expanded)
+}
+
+fn expand_path(shell: &mut Shell, word: &str) -> ExpansionResult<String> {
+    let mut paths = expand_fields(shell, word)?;
+    if paths.len() != 1 {
+        return Err(format!("{}: ambiguous redirect", word));
+    }
+
+    Ok(paths.remove(0))
+}
+
+fn expand_redirect_target(
+    shell: &mut Shell,
+    target: &RedirectTarget,
+) -> ExpansionResult<RedirectTarget> {
+    let target = match target {
+        RedirectTarget::Input { path } => RedirectTarget::Input {
+            path: expand_path(shell, path)?,
+        },
+        RedirectTarget::Output { path, append } => RedirectTarget::Output {
+            path: expand_path(shell, path)?,
+            append: *append,
+        },
+        RedirectTarget::ReadWrite { path } => RedirectTarget::ReadWrite {
+            path: expand_path(shell, path)?,
+        },
+        RedirectTarget::Duplicate { word } => RedirectTarget::Duplicate {
+            word: expand_word(shell, word)?,
+        },
+        RedirectTarget::HereDocument { body, expand: true } => RedirectTarget::HereDocument {
+            body: WordExpander::new(shell, body, false).expand_here_document()?,
+            expand: false,
+        },
+        RedirectTarget::HereDocument { .. } => target.clone(),
+        RedirectTarget::HereString { word } => RedirectTarget::HereString {
+            word: expand_word(shell, word)?,
+        },
+    };
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell_with(parameters: &[&str]) -> Shell {
+        let mut shell = Shell::new();
+        shell.name = "sh".to_string();
+        shell.positional_parameters = parameters.iter().map(|value| value.to_string()).collect();
+        shell.variables.unset("IFS").unwrap();
+        shell
+    }
+
+    fn expand(shell: &mut Shell, word: &str) -> Vec<String> {
+        expand_arguments(shell, &[word.to_string()]).unwrap()
+    }
+
+    #[test]
+    fn applies_parameter_operators() {
+        let mut shell = shell_with(&[]);
+        shell
+            .variables
+            .set("name", "value.txt".to_string())
+            .unwrap();
+
+        assert_eq!(expand(&mut shell, "${#name}"), ["9"]);
+        assert_eq!(expand(&mut shell, "${name%.txt}"), ["value"]);
+        assert_eq!(expand(&mut shell, "${name#*.}"), ["txt"]);
+        assert_eq!(expand(&mut shell, "${name##*[aeu]}"), [".txt"]);
+        assert_eq!(expand(&mut shell, "${name//[aeu]/_}"), ["v_l__.txt"]);
+        assert_eq!(expand(&mut shell, "${name:1:3}"), ["alu"]);
+        assert_eq!(expand(&mut shell, "${name: -3}"), ["txt"]);
+        assert_eq!(expand(&mut shell, "${name:+set}"), ["set"]);
+        assert_eq!(expand(&mut shell, "${missing:-default}"), ["default"]);
+        assert_eq!(expand(&mut shell, "${missing:=assigned}"), ["assigned"]);
+        assert_eq!(expand(&mut shell, "$missing"), ["assigned"]);
+
+        let error = expand_arguments(&mut shell, &["${unset:?}".to_string()]);
+        assert_eq!(error, Err("unset: parameter null or not set".to_string()));
+        assert!(shell.parameter_error);
+    }
+
+    #[test]
+    fn splits_unquoted_expansions_on_ifs() {
+        let mut shell = shell_with(&[]);
+        shell
+            .variables
+            .set("spaced", "  a  b  ".to_string())
+            .unwrap();
+        assert_eq!(expand(&mut shell, "$spaced"), ["a", "b"]);
+        assert_eq!(expand(&mut shell, "\"$spaced\""), ["  a  b  "]);
+
+        shell.variables.set("IFS", ":".to_string()).unwrap();
+        shell.variables.set("list", "a::b:".to_string()).unwrap();
+        assert_eq!(expand(&mut shell, "$list"), ["a", "", "b"]);
+
+        shell.variables.set("IFS", ": ".to_string()).unwrap();
+        shell
+            .variables
+            .set("list", " a : b ::c".to_string())
+            .unwrap();
+        assert_eq!(expand(&mut shell, "$list"), ["a", "b", "", "c"]);
+    }
+
+    #[test]
+    fn expands_positional_parameters() {
+        let mut shell = shell_with(&["a", "b c"]);
+
+        assert_eq!(expand(&mut shell, "\"$@\""), ["a", "b c"]);
+        assert_eq!(expand(&mut shell, "\"x$@y\""), ["xa", "b cy"]);
+        assert_eq!(expand(&mut shell, "$@"), ["a", "b", "c"]);
+        assert_eq!(expand(&mut shell, "\"$*\""), ["a b c"]);
+        assert_eq!(expand(&mut shell, "${#@}"), ["2"]);
+        assert_eq!(expand(&mut shell, "${#*}"), ["2"]);
+        assert_eq!(expand(&mut shell, "\"${@:1:1}\""), ["a"]);
+        assert_eq!(expand(&mut shell, "\"${@:0}\""), ["sh", "a", "b c"]);
+        assert_eq!(expand(&mut shell, "\"${@: -1}\""), ["b c"]);
+        assert_eq!(expand(&mut shell, "\"${*:1}\""), ["a b c"]);
+
+        shell.variables.set("IFS", "-".to_string()).unwrap();
+        assert_eq!(expand(&mut shell, "\"$*\""), ["a-b c"]);
+
+        let mut shell = shell_with(&[]);
+        assert_eq!(expand(&mut shell, "\"$@\""), Vec::<String>::new());
+        assert_eq!(expand(&mut shell, "\"${@:1}\""), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,354 @@
+use std::fs;
+
+const STAR: char = '*';
+const QUESTION: char = '?';
+const OPEN_BRACKET: char = '[';
+const CLOSE_BRACKET: char = ']';
+const SLASH: char = '/';
+const DOT: char = '.';
+
+/// A pattern character and whether it was quoted, quoted characters only ever match themselves.
+pub type PatternChar = (char, bool);
+
+enum BracketItem {
+    Character(char),
+    Range(char, char),
+    Class(String),
+}
+
+struct Bracket {
+    negated: bool,
+    items: Vec<BracketItem>,
+}
+
+impl Bracket {
+    fn parse(pattern: &[PatternChar]) -> Option<(Bracket, usize)> {
+        let mut index = 1;
+        let mut negated = false;
+
+        if let Some(&('!' | '^', false)) = pattern.get(index) {
+            negated = true;
+            index += 1;
+        }
+
+        let mut items = Vec::new();
+        let first = index;
+
+        loop {
+            let &(character, quoted) = pattern.get(index)?;
+
+            if character == CLOSE_BRACKET && !quoted && index != first {
+                return Some((Bracket { negated, items }, index + 1));
+            }
+
+            if character == OPEN_BRACKET && !quoted {
+                if let Some((class, length)) = Bracket::parse_class(&pattern[index..]) {
+                    items.push(BracketItem::Class(class));
+                    index += length;
+                    continue;
+                }
+            }
+
+            match (pattern.get(index + 1), pattern.get(index + 2)) {
+                (Some(&('-', false)), Some(&(end, end_quoted)))
+                    if end != CLOSE_BRACKET || end_quoted =>
+                {
+                    items.push(BracketItem::Range(character, end));
+                    index += 3;
+                }
+                _ => {
+                    items.push(BracketItem::Character(character));
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    fn parse_class(pattern: &[PatternChar]) -> Option<(String, usize)> {
+        if pattern.get(1) != Some(&(':', false)) {
+            return None;
+        }
+
+        let mut name = String::new();
+        for (index, &(character, _)) in pattern.iter().enumerate().skip(2) {
+            if character == ':' {
+                return match pattern.get(index + 1) {
+                    Some(&(CLOSE_BRACKET, false)) => Some((name, index + 2)),
+                    _ => None,
+                };
+            }
+
+            name.push(character);
+        }
+
+        None
+    }
+
+    fn matches(&self, character: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            BracketItem::Character(expected) => character == *expected,
+            BracketItem::Range(start, end) => (*start..=*end).contains(&character),
+            BracketItem::Class(name) => match name.as_str() {
+                "alnum" => character.is_alphanumeric(),
+                "alpha" => character.is_alphabetic(),
+                "blank" => character == ' ' || character == '\t',
+                "cntrl" => character.is_control(),
+                "digit" => character.is_ascii_digit(),
+                "graph" => character.is_ascii_graphic(),
+                "lower" => character.is_lowercase(),
+                "print" => character.is_ascii_graphic() || character == ' ',
+                "punct" => character.is_ascii_punctuation(),
+                "space" => character.is_whitespace(),
+                "upper" => character.is_uppercase(),
+                "xdigit" => character.is_ascii_hexdigit(),
+                _ => false,
+            },
+        });
+
+        found != self.negated
+    }
+}
+
+pub fn has_wildcards(pattern: &[PatternChar]) -> bool {
+    pattern
+        .iter()
+        .enumerate()
+        .any(|(index, &(character, quoted))| match (character, quoted) {
+            (STAR | QUESTION, false) => true,
+            (OPEN_BRACKET, false) => Bracket::parse(&pattern[index..]).is_some(),
+            _ => false,
+        })
+}
+
+/// Matches the first pattern element other than `*` against `character`, returning its length.
+fn match_one(pattern: &[PatternChar], character: char) -> Option<usize> {
+    let &(expected, quoted) = pattern.first()?;
+
+    if !quoted {
+        match expected {
+            QUESTION => return Some(1),
+            OPEN_BRACKET => {
+                if let Some((bracket, length)) = Bracket::parse(pattern) {
+                    return bracket.matches(character).then_some(length);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (expected == character).then_some(1)
+}
+
+/// Only the last `*` seen is ever retried, which keeps matching at O(pattern × text).
+fn match_from(pattern: &[PatternChar], text: &[char]) -> bool {
+    let mut index = 0;
+    let mut position = 0;
+    let mut last_star: Option<(usize, usize)> = None;
+
+    loop {
+        if pattern.get(index) == Some(&(STAR, false)) {
+            index += 1;
+            last_star = Some((index, position));
+            continue;
+        }
+
+        let Some(&character) = text.get(position) else {
+            break;
+        };
+
+        if let Some(length) = match_one(&pattern[index..], character) {
+            index += length;
+            position += 1;
+            continue;
+        }
+
+        match last_star {
+            Some((after_star, start)) => {
+                index = after_star;
+                position = start + 1;
+                last_star = Some((after_star, position));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[index..]
+        .iter()
+        .all(|&element| element == (STAR, false))
+}
+
+pub fn pattern_matches(pattern: &[PatternChar], text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+
+    match_from(pattern, &text)
+}
+
+fn join(directory: &str, name: &str) -> String {
+    if directory.is_empty() {
+        name.to_string()
+    } else if directory.ends_with(SLASH) {
+        format!("{}{}", directory, name)
+    } else {
+        format!("{}{}{}", directory, SLASH, name)
+    }
+}
+
+fn read_matching(directory: &str, component: &[PatternChar]) -> Vec<String> {
+    let path = if directory.is_empty() { "." } else { directory };
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+
+    let explicit_dot = component.first().map(|&(character, _)| character) == Some(DOT);
+
+    let mut matches: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| explicit_dot || !name.starts_with(DOT))
+        .filter(|name| pattern_matches(component, name))
+        .map(|name| join(directory, &name))
+        .collect();
+
+    matches.sort();
+    matches
+}
+
+/// Expands a pattern against the file system, returning the sorted matches or nothing at all.
+pub fn expand_pathname(pattern: &[PatternChar]) -> Vec<String> {
+    let components: Vec<&[PatternChar]> = pattern
+        .split(|&(character, _)| character == SLASH)
+        .collect();
+    let last = components.len() - 1;
+
+    let mut paths = vec![String::new()];
+
+    for (index, component) in components.into_iter().enumerate() {
+        if component.is_empty() {
+            if index == 0 {
+                paths = vec![SLASH.to_string()];
+            } else {
+                paths = paths
+                    .into_iter()
+                    .filter(|path| fs::metadata(path).is_ok_and(|metadata| metadata.is_dir()))
+                    .map(|path| format!("{}{}", path, SLASH))
+                    .collect();
+            }
+
+            continue;
+        }
+
+        if has_wildcards(component) {
+            paths = paths
+                .iter()
+                .flat_map(|directory| read_matching(directory, component))
+                .collect();
+        } else {
+            let name: String = component.iter().map(|&(character, _)| character).collect();
+
+            paths = paths
+                .iter()
+                .map(|directory| join(directory, &name))
+                .filter(|path| index != last || fs::symlink_metadata(path).is_ok())
+                .collect();
+        }
+
+        if paths.is_empty() {
+            break;
+        }
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf, process};
+
+    use super::*;
+
+    /// A scratch directory that is removed again when the test ends, even if it fails.
+    struct TemporaryDirectory(PathBuf);
+
+    impl TemporaryDirectory {
+        fn new(files: &[&str]) -> TemporaryDirectory {
+            let path = env::temp_dir().join(format!("glob-test-{}", process::id()));
+            fs::create_dir_all(&path).unwrap();
+
+            for name in files {
+                fs::write(path.join(name), "").unwrap();
+            }
+
+            TemporaryDirectory(path)
+        }
+    }
+
+    impl Drop for TemporaryDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn pattern(text: &str) -> Vec<PatternChar> {
+        text.chars().map(|character| (character, false)).collect()
+    }
+
+    #[test]
+    fn matches_wildcards_and_brackets() {
+        assert!(pattern_matches(&pattern("a*c"), "abbc"));
+        assert!(pattern_matches(&pattern("a?"), "ab"));
+        assert!(!pattern_matches(&pattern("a?"), "a"));
+        assert!(pattern_matches(&pattern("a[!1]"), "a2"));
+        assert!(!pattern_matches(&pattern("a[!1]"), "a1"));
+        assert!(pattern_matches(&pattern("[[:upper:]]x"), "Bx"));
+        assert!(pattern_matches(&pattern("[]a]"), "]"));
+    }
+
+    #[test]
+    fn backtracks_over_stars() {
+        assert!(pattern_matches(&pattern("*a*b"), "xaxxab"));
+        assert!(pattern_matches(&pattern("a*b*c"), "abcbc"));
+        assert!(pattern_matches(&pattern("**"), ""));
+        assert!(!pattern_matches(&pattern("*a*b"), "xaxxa"));
+        assert!(!pattern_matches(&pattern("a*"), "ba"));
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_exponentially() {
+        let text = "a".repeat(40);
+        let hostile = format!("{}b", "*a".repeat(20));
+
+        assert!(!pattern_matches(&pattern(&hostile), &text));
+        assert!(pattern_matches(
+            &pattern(&format!("{}*", "*a".repeat(20))),
+            &text
+        ));
+    }
+
+    #[test]
+    fn quoted_characters_match_themselves() {
+        let quoted = [('*', true)];
+
+        assert!(!has_wildcards(&quoted));
+        assert!(pattern_matches(&quoted, "*"));
+        assert!(!pattern_matches(&quoted, "x"));
+    }
+
+    #[test]
+    fn skips_hidden_files_unless_the_dot_is_explicit() {
+        let directory = TemporaryDirectory::new(&[".hidden", "a1", "a2", "b1"]);
+
+        let root = directory.0.to_str().unwrap();
+        let expand = |text: &str| expand_pathname(&pattern(&format!("{}/{}", root, text)));
+        let expected = |names: &[&str]| -> Vec<String> {
+            names
+                .iter()
+                .map(|name| format!("{}/{}", root, name))
+                .collect()
+        };
+
+        assert_eq!(expand("*"), expected(&["a1", "a2", "b1"]));
+        assert_eq!(expand(".h*"), expected(&[".hidden"]));
+        assert_eq!(expand("a[!1]"), expected(&["a2"]));
+        assert!(expand("c*").is_empty());
+    }
+}
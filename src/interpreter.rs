@@ -0,0 +1,658 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::io::{AsRawFd, RawFd},
+    process::exit,
+    rc::Rc,
+};
+
+use fork::{fork, Fork};
+
+use crate::{
+    describe_error, evaluate_arithmetic, expand_arguments, expand_pattern, expand_redirects,
+    expand_word, fork_job, parse_argv, pattern_matches, pipe, reset_signals, run_pipeline,
+    run_single, wait_for, wait_foreground, AndOrList, ArithmeticForClause, Assignment, CaseClause,
+    CaseTerminator, Command, CompoundCommand, Connector, Flow, ForClause, IfClause, JobState, List,
+    LoopClause, Pipeline, Redirect, RedirectStreams, Shell, TrapCondition, STATUS_SYNTAX_ERROR,
+};
+
+const NULL_DEVICE: &str = "/dev/null";
+
+pub fn run_list(shell: &mut Shell, list: &List) -> Option<i32> {
+    for and_or in &list.items {
+        if and_or.background {
+            run_background(shell, and_or);
+            continue;
+        }
+
+        if let Some(code) = run_and_or(shell, and_or) {
+            return Some(code);
+        }
+
+        if let Some(code) = run_pending_traps(shell) {
+            return Some(code);
+        }
+
+        // Only the prompt reports finished jobs, elsewhere they are reaped silently.
+        if !shell.interactive {
+            shell.jobs.update();
+        }
+
+        if shell.flow.is_some() {
+            break;
+        }
+    }
+
+    None
+}
+
+pub fn run_and_or(shell: &mut Shell, and_or: &AndOrList) -> Option<i32> {
+    if let Some(code) = run_any(shell, &and_or.first) {
+        return Some(code);
+    }
+
+    let mut last_run = &and_or.first;
+    for (connector, pipeline) in &and_or.rest {
+        if shell.flow.is_some() {
+            break;
+        }
+
+        let succeeded = shell.last_status == 0;
+
+        let should_run = match connector {
+            Connector::And => succeeded,
+            Connector::Or => !succeeded,
+        };
+
+        if should_run {
+            if let Some(code) = run_any(shell, pipeline) {
+                return Some(code);
+            }
+
+            last_run = pipeline;
+        }
+    }
+
+    let last = and_or
+        .rest
+        .last()
+        .map_or(&and_or.first, |(_, pipeline)| pipeline);
+    if shell.last_status != 0 && std::ptr::eq(last_run, last) && raises_error(shell, last) {
+        return run_trap(shell, TrapCondition::Err);
+    }
+
+    None
+}
+
+/// Starts `and_or` in a forked copy of the shell and registers it as a job, without waiting for it.
+fn run_background(shell: &mut Shell, and_or: &AndOrList) {
+    let job_control = shell.terminal.is_some();
+
+    match fork_job(shell, false) {
+        Fork::Parent(child) => {
+            shell.last_background_pid = Some(child);
+            let id = shell
+                .jobs
+                .add(child, and_or.text.clone(), JobState::Running);
+
+            if shell.interactive {
+                eprintln!("[{}] {}", id, child);
+            }
+
+            shell.last_status = 0;
+        }
+        Fork::Child => {
+            if !job_control {
+                if let Ok(null) = File::open(NULL_DEVICE) {
+                    unsafe { libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) };
+                }
+            }
+
+            let code = run_and_or(shell, and_or).unwrap_or(shell.last_status);
+            let _ = io::stdout().flush();
+
+            exit(code);
+        }
+    }
+}
+
+/// Tells whether a failing `pipeline` triggers the `ERR` trap, which conditions, function bodies
+/// and compound commands other than subshells do not, their inner commands having triggered it already.
+fn raises_error(shell: &Shell, pipeline: &Pipeline) -> bool {
+    if shell.condition_depth != 0 || shell.function_depth != 0 || shell.flow.is_some() {
+        return false;
+    }
+
+    !pipeline.negated && !is_grouping(pipeline)
+}
+
+/// Tells whether `pipeline` is a single compound command run by the shell itself, whose status is
+/// the one of the last command inside it.
+fn is_grouping(pipeline: &Pipeline) -> bool {
+    matches!(
+        pipeline.commands.as_slice(),
+        [Command::Compound(compound, _)] if !matches!(compound, CompoundCommand::Subshell(_))
+    )
+}
+
+/// Runs the action trapped on `condition`, leaving `$?` as it was unless the action exits the shell.
+pub fn run_trap(shell: &mut Shell, condition: TrapCondition) -> Option<i32> {
+    if shell.traps.running {
+        return None;
+    }
+
+    let action = match shell.traps.get(condition) {
+        Some(action) if !action.is_empty() => action.clone(),
+        _ => return None,
+    };
+
+    let list = match parse_argv(&action) {
+        Ok(list) => list,
+        Err(error) => {
+            eprintln!("trap: {}", error);
+            return None;
+        }
+    };
+
+    let status = shell.last_status;
+    shell.traps.running = true;
+    let result = run_list(shell, &list);
+    shell.traps.running = false;
+    shell.last_status = status;
+
+    result
+}
+
+/// Runs the actions of the trapped signals received since the last command boundary.
+pub fn run_pending_traps(shell: &mut Shell) -> Option<i32> {
+    if shell.traps.running {
+        return None;
+    }
+
+    for condition in shell.traps.take_pending() {
+        if let Some(code) = run_trap(shell, condition) {
+            return Some(code);
+        }
+    }
+
+    None
+}
+
+/// Runs the `EXIT` trap of a shell about to exit with `code`, returning the code to exit with.
+pub fn run_exit_trap(shell: &mut Shell, code: i32) -> i32 {
+    shell.last_status = code;
+
+    run_trap(shell, TrapCondition::Exit).unwrap_or(code)
+}
+
+fn run_any(shell: &mut Shell, pipeline: &Pipeline) -> Option<i32> {
+    let result = match pipeline.commands.len() {
+        0 => None,
+        1 => {
+            let result = run_command(shell, &pipeline.commands[0]);
+
+            if !is_grouping(pipeline) {
+                shell.pipe_status = vec![shell.last_status];
+            }
+
+            result
+        }
+        _ => {
+            run_pipeline(shell, pipeline);
+            None
+        }
+    };
+
+    if pipeline.negated {
+        shell.last_status = (shell.last_status == 0) as i32;
+    }
+
+    result
+}
+
+pub fn run_command(shell: &mut Shell, command: &Command) -> Option<i32> {
+    match command {
+        Command::Simple(simple) => run_single(shell, simple),
+        Command::Compound(compound, redirects) => {
+            run_redirected(shell, redirects, |shell| run_compound(shell, compound))
+        }
+        Command::FunctionDefinition(definition) => {
+            let body = Rc::new(definition.body.as_ref().clone());
+            shell.functions.insert(definition.name.clone(), body);
+
+            shell.last_status = 0;
+            None
+        }
+    }
+}
+
+fn run_compound(shell: &mut Shell, compound: &CompoundCommand) -> Option<i32> {
+    match compound {
+        CompoundCommand::BraceGroup(list) => run_list(shell, list),
+        CompoundCommand::Arithmetic(expression) => run_arithmetic(shell, expression),
+        CompoundCommand::If(clause) => run_if(shell, clause),
+        CompoundCommand::Loop(clause) => in_loop(shell, |shell| run_loop(shell, clause)),
+        CompoundCommand::For(clause) => in_loop(shell, |shell| run_for(shell, clause)),
+        CompoundCommand::ArithmeticFor(clause) => {
+            in_loop(shell, |shell| run_arithmetic_for(shell, clause))
+        }
+        CompoundCommand::Case(clause) => run_case(shell, clause),
+        CompoundCommand::Subshell(list) => {
+            run_subshell(shell, list);
+            None
+        }
+    }
+}
+
+/// Runs `list` in a forked copy of the shell, so nothing it changes leaks back.
+fn run_subshell(shell: &mut Shell, list: &List) {
+    match fork_job(shell, true) {
+        Fork::Parent(child) => {
+            let items: Vec<&str> = list.items.iter().map(|item| item.text.as_str()).collect();
+            let text = format!("({})", items.join("; "));
+
+            shell.last_status = wait_foreground(shell, child, &text);
+        }
+        Fork::Child => {
+            let code = run_list(shell, list).unwrap_or(shell.last_status);
+            let code = run_exit_trap(shell, code);
+            let _ = io::stdout().flush();
+
+            exit(code);
+        }
+    }
+}
+
+/// Runs `run` with `streams` applied to the shell process itself, then puts the previous descriptors back.
+pub fn run_with_streams(
+    shell: &mut Shell,
+    streams: RedirectStreams,
+    run: impl FnOnce(&mut Shell) -> Option<i32>,
+) -> Option<i32> {
+    if streams.descriptors.is_empty() {
+        return run(shell);
+    }
+
+    if let Some(terminal) = &mut shell.terminal {
+        let fds: Vec<RawFd> = streams.descriptors.keys().map(|fd| *fd as RawFd).collect();
+
+        if let Err(error) = terminal.avoid(&fds) {
+            eprintln!("{}", describe_error(&error));
+            shell.last_status = 1;
+            return None;
+        }
+    }
+
+    let saved = match streams.apply() {
+        Ok(saved) => saved,
+        Err(error) => {
+            eprintln!("{}", describe_error(&error));
+            shell.last_status = 1;
+            return None;
+        }
+    };
+
+    let result = run(shell);
+    saved.restore();
+
+    result
+}
+
+fn run_redirected(
+    shell: &mut Shell,
+    redirects: &[Redirect],
+    run: impl FnOnce(&mut Shell) -> Option<i32>,
+) -> Option<i32> {
+    let streams =
+        expand_redirects(shell, redirects).and_then(|redirects| RedirectStreams::new(&redirects));
+
+    match streams {
+        Ok(streams) => run_with_streams(shell, streams, run),
+        Err(message) => shell.expansion_error(&message),
+    }
+}
+
+/// Calls a function with its own positional parameters and scope for `local` variables.
+pub fn run_function(
+    shell: &mut Shell,
+    body: &Command,
+    arguments: &[String],
+    assignments: &[Assignment],
+) -> Option<i32> {
+    let positional_parameters =
+        std::mem::replace(&mut shell.positional_parameters, arguments[1..].to_vec());
+    let loop_depth = std::mem::take(&mut shell.loop_depth);
+    let return_trap = shell.traps.get(TrapCondition::Return).cloned();
+    shell.function_depth += 1;
+    shell.variables.push_scope();
+
+    let assigned = assignments.iter().try_for_each(|assignment| {
+        shell
+            .variables
+            .make_local(&assignment.name, Some(assignment.value.clone()))?;
+        shell.variables.export(&assignment.name, None)
+    });
+
+    let mut result = match assigned {
+        Ok(()) => run_command(shell, body),
+        Err(message) => {
+            eprintln!("{}", message);
+            shell.last_status = 1;
+            None
+        }
+    };
+
+    if shell.flow == Some(Flow::Return) {
+        shell.flow = None;
+    }
+
+    // Functions do not inherit the `RETURN` trap, only one they set themselves fires.
+    if result.is_none() && shell.traps.get(TrapCondition::Return) != return_trap.as_ref() {
+        result = run_trap(shell, TrapCondition::Return);
+    }
+
+    shell.variables.pop_scope();
+    shell.function_depth -= 1;
+    shell.loop_depth = loop_depth;
+    shell.positional_parameters = positional_parameters;
+
+    result
+}
+
+/// Evaluates `expression`, failing with the code to exit with if the shell should stop.
+fn arithmetic_value(shell: &mut Shell, expression: &str) -> Result<i64, Option<i32>> {
+    let expression = match expand_word(shell, expression) {
+        Ok(expression) => expression,
+        Err(message) => return Err(shell.expansion_error(&message)),
+    };
+
+    match evaluate_arithmetic(shell, &expression) {
+        Ok(value) => Ok(value),
+        Err(error) => {
+            eprintln!("{}: {}", expression.trim(), error);
+            shell.last_status = 1;
+            Err(None)
+        }
+    }
+}
+
+fn run_arithmetic(shell: &mut Shell, expression: &str) -> Option<i32> {
+    shell.last_status = match arithmetic_value(shell, expression) {
+        Ok(0) => 1,
+        Ok(_) => 0,
+        Err(code) => return code,
+    };
+
+    None
+}
+
+/// Runs the condition of an `if` or a loop, where failures do not trigger the `ERR` trap.
+fn run_condition(shell: &mut Shell, condition: &List) -> Option<i32> {
+    shell.condition_depth += 1;
+    let result = run_list(shell, condition);
+    shell.condition_depth -= 1;
+
+    result
+}
+
+fn run_if(shell: &mut Shell, clause: &IfClause) -> Option<i32> {
+    for (condition, body) in &clause.branches {
+        if let Some(code) = run_condition(shell, condition) {
+            return Some(code);
+        }
+
+        if shell.flow.is_some() {
+            return None;
+        }
+
+        if shell.last_status == 0 {
+            return run_list(shell, body);
+        }
+    }
+
+    match &clause.otherwise {
+        Some(body) => run_list(shell, body),
+        None => {
+            shell.last_status = 0;
+            None
+        }
+    }
+}
+
+fn in_loop(shell: &mut Shell, run: impl FnOnce(&mut Shell) -> Option<i32>) -> Option<i32> {
+    shell.loop_depth += 1;
+    let result = run(shell);
+    shell.loop_depth -= 1;
+
+    result
+}
+
+/// Consumes a pending `break` or `continue` aimed at the innermost loop, returning whether to leave it.
+fn leave_loop(shell: &mut Shell) -> bool {
+    match shell.flow {
+        None => false,
+        Some(Flow::Continue(1)) => {
+            shell.flow = None;
+            false
+        }
+        Some(Flow::Break(1)) => {
+            shell.flow = None;
+            true
+        }
+        Some(Flow::Break(count)) => {
+            shell.flow = Some(Flow::Break(count - 1));
+            true
+        }
+        Some(Flow::Continue(count)) => {
+            shell.flow = Some(Flow::Continue(count - 1));
+            true
+        }
+        Some(Flow::Return) => true,
+    }
+}
+
+fn run_loop(shell: &mut Shell, clause: &LoopClause) -> Option<i32> {
+    let mut status = 0;
+
+    loop {
+        if let Some(code) = run_condition(shell, &clause.condition) {
+            return Some(code);
+        }
+
+        if leave_loop(shell) || (shell.last_status == 0) == clause.until {
+            break;
+        }
+
+        if let Some(code) = run_list(shell, &clause.body) {
+            return Some(code);
+        }
+
+        status = shell.last_status;
+
+        if leave_loop(shell) {
+            break;
+        }
+    }
+
+    shell.last_status = status;
+    None
+}
+
+fn run_for(shell: &mut Shell, clause: &ForClause) -> Option<i32> {
+    let words = match &clause.words {
+        Some(words) => match expand_arguments(shell, words) {
+            Ok(words) => words,
+            Err(message) => return shell.expansion_error(&message),
+        },
+        None => shell.positional_parameters.clone(),
+    };
+
+    let mut status = 0;
+
+    for word in words {
+        if let Err(message) = shell.variables.set(&clause.name, word) {
+            eprintln!("{}", message);
+            status = 1;
+            break;
+        }
+
+        if let Some(code) = run_list(shell, &clause.body) {
+            return Some(code);
+        }
+
+        status = shell.last_status;
+
+        if leave_loop(shell) {
+            break;
+        }
+    }
+
+    shell.last_status = status;
+    None
+}
+
+fn run_arithmetic_for(shell: &mut Shell, clause: &ArithmeticForClause) -> Option<i32> {
+    let is_present = |expression: &str| !expression.trim().is_empty();
+
+    if is_present(&clause.initialize) {
+        if let Err(code) = arithmetic_value(shell, &clause.initialize) {
+            return code;
+        }
+    }
+
+    let mut status = 0;
+
+    loop {
+        if is_present(&clause.condition) {
+            match arithmetic_value(shell, &clause.condition) {
+                Err(code) => return code,
+                Ok(0) => break,
+                Ok(_) => {}
+            }
+        }
+
+        if let Some(code) = run_list(shell, &clause.body) {
+            return Some(code);
+        }
+
+        status = shell.last_status;
+
+        if leave_loop(shell) {
+            break;
+        }
+
+        if is_present(&clause.update) {
+            if let Err(code) = arithmetic_value(shell, &clause.update) {
+                return code;
+            }
+        }
+    }
+
+    shell.last_status = status;
+    None
+}
+
+fn run_case(shell: &mut Shell, clause: &CaseClause) -> Option<i32> {
+    let word = match expand_word(shell, &clause.word) {
+        Ok(word) => word,
+        Err(message) => return shell.expansion_error(&message),
+    };
+
+    let mut status = 0;
+    let mut falling_through = false;
+
+    for item in &clause.items {
+        if !falling_through {
+            let mut matched = false;
+
+            for pattern in &item.patterns {
+                match expand_pattern(shell, pattern) {
+                    Ok(pattern) if pattern_matches(&pattern, &word) => {
+                        matched = true;
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(message) => return shell.expansion_error(&message),
+                }
+            }
+
+            if !matched {
+                continue;
+            }
+        }
+
+        shell.last_status = 0;
+        if let Some(code) = run_list(shell, &item.body) {
+            return Some(code);
+        }
+
+        status = shell.last_status;
+
+        if shell.flow.is_some() {
+            break;
+        }
+
+        match item.terminator {
+            CaseTerminator::Break => break,
+            CaseTerminator::FallThrough => falling_through = true,
+            CaseTerminator::Continue => falling_through = false,
+        }
+    }
+
+    shell.last_status = status;
+    None
+}
+
+/// Runs `source` in a forked copy of the shell and returns what it printed, minus trailing newlines.
+pub fn run_substitution(shell: &mut Shell, source: &str) -> String {
+    let list = match parse_argv(source) {
+        Ok(list) => list,
+        Err(error) => {
+            eprintln!("{}", error);
+            shell.last_status = STATUS_SYNTAX_ERROR;
+            return String::new();
+        }
+    };
+
+    let (mut read, write) = match pipe() {
+        Ok(pipe) => pipe,
+        Err(error) => {
+            eprintln!("pipe: {}", describe_error(&error));
+            shell.last_status = 1;
+            return String::new();
+        }
+    };
+
+    match fork().unwrap() {
+        Fork::Parent(child) => {
+            drop(write);
+
+            let mut output = Vec::new();
+            let _ = read.read_to_end(&mut output);
+            shell.last_status = wait_for(child);
+            shell.substitution_status = Some(shell.last_status);
+
+            let mut output = String::from_utf8_lossy(&output).into_owned();
+            output.truncate(output.trim_end_matches('\n').len());
+            output
+        }
+        Fork::Child => {
+            drop(read);
+            shell.terminal = None;
+            shell.traps.enter_subshell();
+
+            if shell.interactive {
+                reset_signals(&shell.traps);
+                shell.interactive = false;
+            }
+
+            unsafe { libc::dup2(write.as_raw_fd(), libc::STDOUT_FILENO) };
+            drop(write);
+
+            let code = run_list(shell, &list).unwrap_or(shell.last_status);
+            let _ = io::stdout().flush();
+
+            exit(code);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    io::{self, Write},
+    mem::MaybeUninit,
+    os::unix::{io::RawFd, process::ExitStatusExt},
+    process::ExitStatus,
+};
+
+use fork::{fork, Fork};
+
+use crate::{status_code, Shell, Traps, SHELL_DESCRIPTOR_BASE, STATUS_SIGNAL_BASE};
+
+/// Signals an interactive shell ignores so that the keyboard only reaches the foreground job.
+const INTERACTIVE_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP];
+
+/// Signals a shell doing job control ignores, and hands back to the default in its children.
+const JOB_CONTROL_SIGNALS: [libc::c_int; 3] = [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped(libc::c_int),
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    pub pid: i32,
+    pub command: String,
+    pub state: JobState,
+}
+
+impl Job {
+    pub fn describe_state(&self) -> String {
+        match self.state {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped(signal) => signal_name(signal),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Exit statuses of the jobs reaped before `wait` asked for them, by pid.
+    finished: HashMap<i32, i32>,
+}
+
+impl Jobs {
+    pub fn add(&mut self, pid: i32, command: String, state: JobState) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pid,
+            command,
+            state,
+        });
+
+        id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_by_pid(&self, pid: i32) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.pid == pid)
+    }
+
+    /// Takes the saved exit status of a job that was reaped already.
+    pub fn take_finished(&mut self, pid: i32) -> Option<i32> {
+        self.finished.remove(&pid)
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.finished.clear();
+    }
+
+    pub fn remove(&mut self, pid: i32) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.pid == pid)?;
+
+        Some(self.jobs.remove(index))
+    }
+
+    /// Changes the state of a job and makes it the current one.
+    pub fn set_state(&mut self, pid: i32, state: JobState) {
+        if let Some(mut job) = self.remove(pid) {
+            job.state = state;
+            self.jobs.push(job);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// `+` marks the current job, the most recent one, and `-` the one before it.
+    pub fn marker(&self, id: usize) -> char {
+        let mut recent = self.jobs.iter().rev().map(|job| job.id);
+
+        if recent.next() == Some(id) {
+            '+'
+        } else if recent.next() == Some(id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
+    /// Resolves a job spec: `%n`, `%+` or `%%` for the current job, `%-` for the previous one,
+    /// `%string` for a command starting with `string` and `%?string` for one containing it.
+    pub fn find(&self, spec: &str) -> Option<&Job> {
+        let spec = spec.strip_prefix('%').unwrap_or(spec);
+        let mut recent = self.jobs.iter().rev();
+
+        match spec {
+            "" | "+" | "%" => recent.next(),
+            "-" => recent.nth(1),
+            _ if spec.chars().all(|character| character.is_ascii_digit()) => {
+                self.get(spec.parse().ok()?)
+            }
+            _ => match spec.strip_prefix('?') {
+                Some(text) => recent.find(|job| job.command.contains(text)),
+                None => recent.find(|job| job.command.starts_with(spec)),
+            },
+        }
+    }
+
+    /// Polls every job without blocking, forgetting the finished ones, and returns
+    /// those that finished or got stopped since the last call with how to describe them.
+    pub fn update(&mut self) -> Vec<(Job, char, String)> {
+        let mut changes = Vec::new();
+
+        for job in &self.jobs {
+            let mut status = 0;
+            let flags = libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED;
+            if unsafe { libc::waitpid(job.pid, &mut status, flags) } != job.pid {
+                continue;
+            }
+
+            let state = if libc::WIFSTOPPED(status) {
+                Some(JobState::Stopped(libc::WSTOPSIG(status)))
+            } else if libc::WIFCONTINUED(status) {
+                Some(JobState::Running)
+            } else {
+                None
+            };
+
+            changes.push((job.clone(), self.marker(job.id), status, state));
+        }
+
+        let mut notifications = Vec::new();
+        for (mut job, marker, status, state) in changes {
+            match state {
+                Some(state) => {
+                    self.set_state(job.pid, state);
+                    job.state = state;
+
+                    if let JobState::Stopped(_) = state {
+                        let description = job.describe_state();
+                        notifications.push((job, marker, description));
+                    }
+                }
+                None => {
+                    self.remove(job.pid);
+
+                    let status = ExitStatus::from_raw(status);
+                    self.finished.insert(job.pid, status_code(status));
+
+                    let description = describe_status(status);
+                    notifications.push((job, marker, description));
+                }
+            }
+        }
+
+        notifications
+    }
+}
+
+/// The controlling terminal of an interactive shell doing job control.
+#[derive(Clone, Copy)]
+pub struct Terminal {
+    fd: RawFd,
+    shell_group: libc::pid_t,
+    modes: libc::termios,
+}
+
+impl Terminal {
+    /// Puts the shell in its own process group in front of the terminal, or gives up if stdin is not one.
+    pub fn acquire() -> Option<Terminal> {
+        if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+            return None;
+        }
+
+        let fd = unsafe {
+            libc::fcntl(
+                libc::STDIN_FILENO,
+                libc::F_DUPFD_CLOEXEC,
+                SHELL_DESCRIPTOR_BASE,
+            )
+        };
+        if fd == -1 {
+            return None;
+        }
+
+        for signal in JOB_CONTROL_SIGNALS {
+            unsafe { libc::signal(signal, libc::SIG_IGN) };
+        }
+
+        let shell_group = unsafe { libc::getpid() };
+        unsafe {
+            libc::setpgid(0, 0);
+            libc::tcsetpgrp(fd, shell_group);
+        }
+
+        let mut modes = MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(fd, modes.as_mut_ptr()) } == -1 {
+            return None;
+        }
+
+        Some(Terminal {
+            fd,
+            shell_group,
+            modes: unsafe { modes.assume_init() },
+        })
+    }
+
+    pub fn give_to(&self, group: libc::pid_t) {
+        unsafe { libc::tcsetpgrp(self.fd, group) };
+    }
+
+    /// Takes the terminal back from a job, with the modes the shell had before it ran.
+    pub fn reclaim(&self) {
+        unsafe {
+            libc::tcsetpgrp(self.fd, self.shell_group);
+            libc::tcsetattr(self.fd, libc::TCSADRAIN, &self.modes);
+        }
+    }
+
+    /// Moves the terminal descriptor above `fds` if it is one of them, so that redirections
+    /// about to replace those in the shell process leave the terminal alone.
+    pub fn avoid(&mut self, fds: &[RawFd]) -> io::Result<()> {
+        if !fds.contains(&self.fd) {
+            return Ok(());
+        }
+
+        let lowest = fds.iter().max().map_or(0, |fd| fd + 1);
+        let fd = unsafe { libc::fcntl(self.fd, libc::F_DUPFD_CLOEXEC, lowest) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        unsafe { libc::close(self.fd) };
+        self.fd = fd;
+
+        Ok(())
+    }
+
+    /// Called from the first process of a job to move it into a process group of its own.
+    pub fn enter_group(&self, foreground: bool) {
+        unsafe {
+            libc::setpgid(0, 0);
+
+            if foreground {
+                libc::tcsetpgrp(self.fd, libc::getpgrp());
+            }
+        }
+    }
+}
+
+/// Tells whether the shell ignores `signal` by itself, when no trap says otherwise.
+pub fn shell_ignores(signal: libc::c_int, interactive: bool) -> bool {
+    interactive && (INTERACTIVE_SIGNALS.contains(&signal) || JOB_CONTROL_SIGNALS.contains(&signal))
+}
+
+pub fn ignore_signals() {
+    for signal in INTERACTIVE_SIGNALS {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+/// Restores the default dispositions of the signals the shell ignores, before running a command,
+/// except for those a trap ignores, which stay ignored.
+pub fn reset_signals(traps: &Traps) {
+    for signal in INTERACTIVE_SIGNALS.iter().chain(&JOB_CONTROL_SIGNALS) {
+        if !traps.ignores(*signal) {
+            unsafe { libc::signal(*signal, libc::SIG_DFL) };
+        }
+    }
+}
+
+/// Forks the shell to run a job, in a process group of its own when job control is on.
+pub fn fork_job(shell: &mut Shell, foreground: bool) -> Fork {
+    let _ = io::stdout().flush();
+
+    match fork().unwrap() {
+        Fork::Parent(child) => {
+            if shell.terminal.is_some() {
+                unsafe { libc::setpgid(child, child) };
+            }
+
+            Fork::Parent(child)
+        }
+        Fork::Child => {
+            if let Some(terminal) = shell.terminal.take() {
+                terminal.enter_group(foreground);
+            }
+
+            if shell.interactive {
+                reset_signals(&shell.traps);
+            }
+
+            shell.jobs = Jobs::default();
+            shell.interactive = false;
+            shell.traps.enter_subshell();
+
+            Fork::Child
+        }
+    }
+}
+
+/// Waits for a foreground job, then takes the terminal back and keeps the job around if it got stopped.
+pub fn wait_foreground(shell: &mut Shell, pid: i32, command: &str) -> i32 {
+    let flags = match &shell.terminal {
+        Some(terminal) => {
+            unsafe { libc::setpgid(pid, pid) };
+            terminal.give_to(pid);
+
+            libc::WUNTRACED
+        }
+        None => 0,
+    };
+
+    let mut status = 0;
+    let result = loop {
+        let result = unsafe { libc::waitpid(pid, &mut status, flags) };
+
+        if result != -1 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            break result;
+        }
+    };
+
+    if let Some(terminal) = &shell.terminal {
+        terminal.reclaim();
+    }
+
+    if result == -1 {
+        return 1;
+    }
+
+    if libc::WIFSTOPPED(status) {
+        let signal = libc::WSTOPSIG(status);
+
+        match shell.jobs.get_by_pid(pid) {
+            Some(_) => shell.jobs.set_state(pid, JobState::Stopped(signal)),
+            None => {
+                shell
+                    .jobs
+                    .add(pid, command.to_string(), JobState::Stopped(signal));
+            }
+        }
+
+        if let Some(job) = shell.jobs.get_by_pid(pid) {
+            eprintln!();
+            eprintln!("{}", format_job(job, '+', &job.describe_state()));
+        }
+
+        return STATUS_SIGNAL_BASE + signal;
+    }
+
+    shell.jobs.remove(pid);
+
+    let status = ExitStatus::from_raw(status);
+    if shell.pid == std::process::id() {
+        report_signal(status, shell.interactive);
+    }
+
+    if let (Some(_), Some(libc::SIGINT)) = (&shell.terminal, status.signal()) {
+        shell.traps.notice(libc::SIGINT);
+    }
+
+    status_code(status)
+}
+
+/// Tells why a foreground job got killed, like "Killed" or "Quit (core dumped)".
+fn report_signal(status: ExitStatus, interactive: bool) {
+    match status.signal() {
+        None | Some(libc::SIGPIPE) => {}
+        Some(libc::SIGINT) if interactive => eprintln!(),
+        Some(libc::SIGINT) => {}
+        Some(signal) if status.core_dumped() => eprintln!("{} (core dumped)", signal_name(signal)),
+        Some(signal) => eprintln!("{}", signal_name(signal)),
+    }
+}
+
+fn signal_name(signal: libc::c_int) -> String {
+    unsafe { CStr::from_ptr(libc::strsignal(signal)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn describe_status(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(0), _) => "Done".to_string(),
+        (Some(code), _) => format!("Exit {}", code),
+        (None, Some(signal)) => signal_name(signal),
+        (None, None) => "Unknown".to_string(),
+    }
+}
+
+pub fn format_job(job: &Job, marker: char, state: &str) -> String {
+    format!("[{}]{}  {:<24}{}", job.id, marker, state, job.command)
+}
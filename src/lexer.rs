@@ -0,0 +1,877 @@
+use crate::{ParseResult, SyntaxError, SyntaxErrorKind};
+
+const SPACE: char = ' ';
+const TAB: char = '\t';
+const NEWLINE: char = '\n';
+const SINGLE: char = '\'';
+const DOUBLE: char = '"';
+const BACKSLASH: char = '\\';
+const DOLLAR: char = '$';
+const BACKTICK: char = '`';
+const OPEN_PARENTHESIS: char = '(';
+const CLOSE_PARENTHESIS: char = ')';
+const HASH: char = '#';
+const OPEN_BRACE: char = '{';
+const CLOSE_BRACE: char = '}';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Pipe,
+    Or,
+    And,
+    Ampersand,
+    Semicolon,
+    DoubleSemicolon,
+    SemicolonAnd,
+    DoubleSemicolonAnd,
+    LeftParenthesis,
+    RightParenthesis,
+    Less,
+    DoubleLess,
+    DoubleLessDash,
+    TripleLess,
+    LessAnd,
+    LessGreat,
+    Great,
+    DoubleGreat,
+    GreatAnd,
+    AndGreat,
+    AndDoubleGreat,
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Pipe => "|",
+            Operator::Or => "||",
+            Operator::And => "&&",
+            Operator::Ampersand => "&",
+            Operator::Semicolon => ";",
+            Operator::DoubleSemicolon => ";;",
+            Operator::SemicolonAnd => ";&",
+            Operator::DoubleSemicolonAnd => ";;&",
+            Operator::LeftParenthesis => "(",
+            Operator::RightParenthesis => ")",
+            Operator::Less => "<",
+            Operator::DoubleLess => "<<",
+            Operator::DoubleLessDash => "<<-",
+            Operator::TripleLess => "<<<",
+            Operator::LessAnd => "<&",
+            Operator::LessGreat => "<>",
+            Operator::Great => ">",
+            Operator::DoubleGreat => ">>",
+            Operator::GreatAnd => ">&",
+            Operator::AndGreat => "&>",
+            Operator::AndDoubleGreat => "&>>",
+        }
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        matches!(
+            self,
+            Operator::Less
+                | Operator::DoubleLess
+                | Operator::DoubleLessDash
+                | Operator::TripleLess
+                | Operator::LessAnd
+                | Operator::LessGreat
+                | Operator::Great
+                | Operator::DoubleGreat
+                | Operator::GreatAnd
+                | Operator::AndGreat
+                | Operator::AndDoubleGreat
+        )
+    }
+
+    pub fn is_case_terminator(&self) -> bool {
+        matches!(
+            self,
+            Operator::DoubleSemicolon | Operator::SemicolonAnd | Operator::DoubleSemicolonAnd
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Word(String),
+    Operator(Operator, Option<u32>),
+    HereDocument { body: String, expand: bool },
+    Arithmetic(String),
+    Newline,
+    End,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub offset: usize,
+    pub end: usize,
+}
+
+impl Token {
+    pub fn as_str(&self) -> &str {
+        match &self.kind {
+            TokenKind::Word(word) => word,
+            TokenKind::Operator(operator, _) => operator.as_str(),
+            TokenKind::HereDocument { .. } => "here-document",
+            TokenKind::Arithmetic(_) => "((",
+            TokenKind::Newline => "newline",
+            TokenKind::End => "end of file",
+        }
+    }
+
+    pub fn is_word(&self, expected: &str) -> bool {
+        matches!(&self.kind, TokenKind::Word(word) if word == expected)
+    }
+
+    pub fn is_operator(&self, expected: Operator) -> bool {
+        matches!(&self.kind, TokenKind::Operator(operator, _) if *operator == expected)
+    }
+}
+
+struct PendingHereDocument {
+    index: usize,
+    offset: usize,
+    delimiter: String,
+    expand: bool,
+    strip_tabs: bool,
+}
+
+impl PendingHereDocument {
+    /// Starts a here-document when `token` is the delimiter that follows a `<<` or `<<-`.
+    fn start(previous: Option<&Token>, token: &Token, index: usize) -> Option<PendingHereDocument> {
+        let strip_tabs = match previous.map(|previous| &previous.kind) {
+            Some(TokenKind::Operator(Operator::DoubleLess, _)) => false,
+            Some(TokenKind::Operator(Operator::DoubleLessDash, _)) => true,
+            _ => return None,
+        };
+
+        let TokenKind::Word(word) = &token.kind else {
+            return None;
+        };
+        let (delimiter, quoted) = remove_quotes(word);
+
+        Some(PendingHereDocument {
+            index,
+            offset: token.offset,
+            delimiter,
+            expand: !quoted,
+            strip_tabs,
+        })
+    }
+}
+
+/// Where a `case` inside a command substitution is, so that the `)` ending a pattern
+/// is not taken for the one closing the substitution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CaseState {
+    Subject,
+    In,
+    Pattern,
+    Body,
+}
+
+/// Reserved words after which the next word starts a command again.
+const COMMAND_PREFIXES: [&str; 9] = [
+    "if", "then", "elif", "else", "while", "until", "do", "{", "!",
+];
+
+fn remove_quotes(word: &str) -> (String, bool) {
+    let mut builder = String::new();
+    let mut quoted = false;
+    let mut quote: Option<char> = None;
+    let mut chars = word.chars();
+
+    while let Some(character) = chars.next() {
+        match (character, quote) {
+            (SINGLE | DOUBLE, None) => {
+                quoted = true;
+                quote = Some(character);
+            }
+            (_, Some(open)) if character == open => quote = None,
+            (BACKSLASH, None | Some(DOUBLE)) => {
+                quoted = true;
+
+                if let Some(escaped) = chars.next() {
+                    builder.push(escaped);
+                }
+            }
+            _ => builder.push(character),
+        }
+    }
+
+    (builder, quoted)
+}
+
+pub struct Lexer<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer { input, position: 0 }
+    }
+
+    pub fn tokenize(mut self) -> ParseResult<Vec<Token>> {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut pending: Vec<PendingHereDocument> = Vec::new();
+
+        loop {
+            let token = self.next_token()?;
+
+            if let Some(here_document) =
+                PendingHereDocument::start(tokens.last(), &token, tokens.len())
+            {
+                pending.push(here_document);
+            }
+
+            match &token.kind {
+                TokenKind::Newline => {
+                    for here_document in pending.drain(..) {
+                        let body = self.here_document_body(&here_document)?;
+
+                        tokens[here_document.index].kind = TokenKind::HereDocument {
+                            body,
+                            expand: here_document.expand,
+                        };
+                    }
+                }
+                TokenKind::End => {
+                    if let Some(here_document) = pending.first() {
+                        return Err(SyntaxError::new(
+                            SyntaxErrorKind::UnterminatedHereDocument(
+                                here_document.delimiter.clone(),
+                            ),
+                            self.input,
+                            here_document.offset,
+                        ));
+                    }
+
+                    tokens.push(token);
+                    return Ok(tokens);
+                }
+                _ => {}
+            }
+
+            tokens.push(token);
+        }
+    }
+
+    fn here_document_body(&mut self, here_document: &PendingHereDocument) -> ParseResult<String> {
+        let mut body = String::new();
+
+        while self.position < self.input.len() {
+            let rest = &self.input[self.position..];
+            let (mut line, consumed) = match rest.find(NEWLINE) {
+                Some(index) => (&rest[..index], index + 1),
+                None => (rest, rest.len()),
+            };
+
+            self.position += consumed;
+
+            if here_document.strip_tabs {
+                line = line.trim_start_matches(TAB);
+            }
+
+            if line == here_document.delimiter {
+                return Ok(body);
+            }
+
+            body.push_str(line);
+            body.push(NEWLINE);
+        }
+
+        Err(SyntaxError::new(
+            SyntaxErrorKind::UnterminatedHereDocument(here_document.delimiter.clone()),
+            self.input,
+            here_document.offset,
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.input[self.position..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let character = self.peek()?;
+        self.position += character.len_utf8();
+
+        Some(character)
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.advance();
+            return true;
+        }
+
+        false
+    }
+
+    fn skip_blanks_and_comments(&mut self) {
+        while let Some(character) = self.peek() {
+            match character {
+                SPACE | TAB => {
+                    self.advance();
+                }
+                BACKSLASH if self.peek_second() == Some(NEWLINE) => {
+                    self.advance();
+                    self.advance();
+                }
+                HASH => {
+                    while !matches!(self.peek(), None | Some(NEWLINE)) {
+                        self.advance();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> ParseResult<Token> {
+        self.skip_blanks_and_comments();
+
+        let offset = self.position;
+        let kind = match self.peek() {
+            None => TokenKind::End,
+            Some(NEWLINE) => {
+                self.advance();
+                TokenKind::Newline
+            }
+            Some(character) => match self.arithmetic() {
+                Some(expression) => TokenKind::Arithmetic(expression),
+                None => match self.operator(character) {
+                    Some(operator) => TokenKind::Operator(operator, None),
+                    None => self.word()?,
+                },
+            },
+        };
+
+        Ok(Token {
+            kind,
+            offset,
+            end: self.position,
+        })
+    }
+
+    /// Reads `(( expression ))` when the double parenthesis is closed by a matching `))`.
+    fn arithmetic(&mut self) -> Option<String> {
+        let rest = &self.input[self.position..];
+        let inner = rest.strip_prefix("((")?;
+
+        let mut depth = 0;
+        for (index, character) in inner.char_indices() {
+            match character {
+                OPEN_PARENTHESIS => depth += 1,
+                CLOSE_PARENTHESIS if depth > 0 => depth -= 1,
+                CLOSE_PARENTHESIS => {
+                    if !inner[index + 1..].starts_with(CLOSE_PARENTHESIS) {
+                        return None;
+                    }
+
+                    self.position += 2 + index + 2;
+                    return Some(inner[..index].to_string());
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn operator(&mut self, character: char) -> Option<Operator> {
+        let operator = match character {
+            '|' => {
+                self.advance();
+
+                if self.next_if('|') {
+                    Operator::Or
+                } else {
+                    Operator::Pipe
+                }
+            }
+            '&' => {
+                self.advance();
+
+                if self.next_if('&') {
+                    Operator::And
+                } else if self.next_if('>') {
+                    if self.next_if('>') {
+                        Operator::AndDoubleGreat
+                    } else {
+                        Operator::AndGreat
+                    }
+                } else {
+                    Operator::Ampersand
+                }
+            }
+            ';' => {
+                self.advance();
+
+                if self.next_if('&') {
+                    Operator::SemicolonAnd
+                } else if !self.next_if(';') {
+                    Operator::Semicolon
+                } else if self.next_if('&') {
+                    Operator::DoubleSemicolonAnd
+                } else {
+                    Operator::DoubleSemicolon
+                }
+            }
+            '(' => {
+                self.advance();
+                Operator::LeftParenthesis
+            }
+            ')' => {
+                self.advance();
+                Operator::RightParenthesis
+            }
+            '<' | '>' => self.redirection(),
+            _ => return None,
+        };
+
+        Some(operator)
+    }
+
+    fn redirection(&mut self) -> Operator {
+        if self.advance() == Some('<') {
+            if self.next_if('&') {
+                Operator::LessAnd
+            } else if self.next_if('>') {
+                Operator::LessGreat
+            } else if !self.next_if('<') {
+                Operator::Less
+            } else if self.next_if('<') {
+                Operator::TripleLess
+            } else if self.next_if('-') {
+                Operator::DoubleLessDash
+            } else {
+                Operator::DoubleLess
+            }
+        } else if self.next_if('>') {
+            Operator::DoubleGreat
+        } else if self.next_if('&') {
+            Operator::GreatAnd
+        } else {
+            Operator::Great
+        }
+    }
+
+    fn is_word_boundary(character: char) -> bool {
+        matches!(
+            character,
+            SPACE | TAB | NEWLINE | '|' | '&' | ';' | '(' | ')' | '<' | '>'
+        )
+    }
+
+    fn word(&mut self) -> ParseResult<TokenKind> {
+        let mut builder = String::new();
+
+        while let Some(character) = self.peek() {
+            if Lexer::is_word_boundary(character) {
+                if matches!(character, '<' | '>')
+                    && !builder.is_empty()
+                    && builder.chars().all(|c| c.is_ascii_digit())
+                {
+                    let fd = builder.parse::<u32>().ok();
+                    return Ok(TokenKind::Operator(self.redirection(), fd));
+                }
+
+                break;
+            }
+
+            let start = self.position;
+            self.advance();
+            builder.push(character);
+
+            match character {
+                SINGLE => self.take_until(&mut builder, start, SINGLE, false)?,
+                DOUBLE => self.take_until(&mut builder, start, DOUBLE, true)?,
+                BACKSLASH => match self.advance() {
+                    Some(NEWLINE) => {
+                        builder.pop();
+                    }
+                    Some(escaped) => builder.push(escaped),
+                    None => {
+                        return Err(SyntaxError::new(
+                            SyntaxErrorKind::UnexpectedEnd,
+                            self.input,
+                            start,
+                        ))
+                    }
+                },
+                BACKTICK => self.take_until(&mut builder, start, BACKTICK, true)?,
+                DOLLAR if self.peek() == Some(OPEN_BRACE) => {
+                    self.take_balanced(&mut builder, start, OPEN_BRACE, CLOSE_BRACE)?
+                }
+                DOLLAR if self.peek() == Some(OPEN_PARENTHESIS) => {
+                    self.take_substitution(&mut builder, start)?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(TokenKind::Word(builder))
+    }
+
+    fn take_until(
+        &mut self,
+        builder: &mut String,
+        start: usize,
+        end: char,
+        escapable: bool,
+    ) -> ParseResult<()> {
+        while let Some(character) = self.advance() {
+            builder.push(character);
+
+            if character == end {
+                return Ok(());
+            }
+
+            if escapable && character == DOLLAR {
+                match self.peek() {
+                    Some(OPEN_PARENTHESIS) => self.take_substitution(builder, start)?,
+                    Some(OPEN_BRACE) => {
+                        self.take_balanced(builder, start, OPEN_BRACE, CLOSE_BRACE)?
+                    }
+                    _ => {}
+                }
+
+                continue;
+            }
+
+            if escapable && character == BACKSLASH {
+                match self.advance() {
+                    Some(NEWLINE) => {
+                        builder.pop();
+                    }
+                    Some(escaped) => builder.push(escaped),
+                    None => break,
+                }
+            }
+        }
+
+        Err(SyntaxError::new(
+            SyntaxErrorKind::Unterminated(end),
+            self.input,
+            start,
+        ))
+    }
+
+    /// Takes a `$(...)` command substitution, or a `$((...))` arithmetic expansion.
+    fn take_substitution(&mut self, builder: &mut String, start: usize) -> ParseResult<()> {
+        if self.input[self.position..].starts_with("((") {
+            return self.take_balanced(builder, start, OPEN_PARENTHESIS, CLOSE_PARENTHESIS);
+        }
+
+        let open = self.position;
+        self.advance();
+        self.skip_substitution(start)?;
+        builder.push_str(&self.input[open..self.position]);
+
+        Ok(())
+    }
+
+    /// Lexes the commands of a command substitution up to and including the `)` that closes
+    /// it, that is the first one not nested, quoted, commented out or ending a `case` pattern.
+    fn skip_substitution(&mut self, start: usize) -> ParseResult<()> {
+        let mut depth = 0;
+        let mut cases: Vec<CaseState> = Vec::new();
+        let mut starts_command = true;
+        let mut previous: Option<Token> = None;
+        let mut pending: Vec<PendingHereDocument> = Vec::new();
+
+        loop {
+            let token = self.next_token()?;
+
+            if let Some(here_document) = PendingHereDocument::start(previous.as_ref(), &token, 0) {
+                pending.push(here_document);
+            }
+
+            let case = cases.last().copied();
+            starts_command = match &token.kind {
+                TokenKind::End => break,
+                TokenKind::Newline => {
+                    for here_document in pending.drain(..) {
+                        self.here_document_body(&here_document)?;
+                    }
+
+                    true
+                }
+                TokenKind::Word(word) => match case {
+                    Some(CaseState::Subject) => {
+                        cases.pop();
+                        cases.push(CaseState::In);
+                        false
+                    }
+                    Some(CaseState::In) if word == "in" => {
+                        cases.pop();
+                        cases.push(CaseState::Pattern);
+                        true
+                    }
+                    Some(CaseState::Pattern | CaseState::Body)
+                        if starts_command && word == "esac" =>
+                    {
+                        cases.pop();
+                        false
+                    }
+                    Some(CaseState::In | CaseState::Pattern) => false,
+                    _ if starts_command && word == "case" => {
+                        cases.push(CaseState::Subject);
+                        false
+                    }
+                    _ => starts_command && COMMAND_PREFIXES.contains(&word.as_str()),
+                },
+                TokenKind::Operator(Operator::RightParenthesis, _) => {
+                    if case == Some(CaseState::Pattern) {
+                        cases.pop();
+                        cases.push(CaseState::Body);
+                    } else if depth == 0 {
+                        return Ok(());
+                    } else {
+                        depth -= 1;
+                    }
+
+                    true
+                }
+                TokenKind::Operator(Operator::LeftParenthesis, _) => {
+                    if case != Some(CaseState::Pattern) {
+                        depth += 1;
+                    }
+
+                    true
+                }
+                TokenKind::Operator(operator, _) if operator.is_case_terminator() => {
+                    if case == Some(CaseState::Body) {
+                        cases.pop();
+                        cases.push(CaseState::Pattern);
+                    }
+
+                    true
+                }
+                TokenKind::Operator(Operator::Pipe, _) if case == Some(CaseState::Pattern) => false,
+                TokenKind::Operator(operator, _) => !operator.is_redirection(),
+                TokenKind::HereDocument { .. } | TokenKind::Arithmetic(_) => false,
+            };
+
+            previous = Some(token);
+        }
+
+        Err(SyntaxError::new(
+            SyntaxErrorKind::Unterminated(CLOSE_PARENTHESIS),
+            self.input,
+            start,
+        ))
+    }
+
+    fn take_balanced(
+        &mut self,
+        builder: &mut String,
+        start: usize,
+        open: char,
+        close: char,
+    ) -> ParseResult<()> {
+        let mut depth = 0;
+
+        while let Some(character) = self.advance() {
+            let position = self.position - character.len_utf8();
+            builder.push(character);
+
+            match character {
+                _ if character == open => depth += 1,
+                _ if character == close => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                SINGLE => self.take_until(builder, position, SINGLE, false)?,
+                DOUBLE => self.take_until(builder, position, DOUBLE, true)?,
+                BACKTICK => self.take_until(builder, position, BACKTICK, true)?,
+                BACKSLASH => {
+                    if let Some(escaped) = self.advance() {
+                        builder.push(escaped);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err(SyntaxError::new(
+            SyntaxErrorKind::Unterminated(close),
+            self.input,
+            start,
+        ))
+    }
+}
+
+/// Finds the `)` that closes a command substitution in `text`, which starts right after its `$(`.
+pub fn substitution_end(text: &str) -> Option<usize> {
+    let mut lexer = Lexer::new(text);
+    lexer.skip_substitution(0).ok()?;
+
+    Some(lexer.position - CLOSE_PARENTHESIS.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<String> {
+        Lexer::new(input)
+            .tokenize()
+            .unwrap()
+            .iter()
+            .map(|token| token.as_str().to_string())
+            .collect()
+    }
+
+    fn error(input: &str) -> SyntaxErrorKind {
+        Lexer::new(input).tokenize().unwrap_err().kind
+    }
+
+    #[test]
+    fn keeps_quotes_and_escapes_in_words() {
+        assert_eq!(
+            tokens(r#"echo 'a b' "c $d" e\ f"#),
+            ["echo", "'a b'", "\"c $d\"", "e\\ f", "end of file"]
+        );
+        assert_eq!(tokens("a\\\nb # c )"), ["ab", "end of file"]);
+    }
+
+    #[test]
+    fn splits_operators() {
+        assert_eq!(
+            tokens("a|b||c&&d;e&\n"),
+            [
+                "a",
+                "|",
+                "b",
+                "||",
+                "c",
+                "&&",
+                "d",
+                ";",
+                "e",
+                "&",
+                "newline",
+                "end of file"
+            ]
+        );
+        assert_eq!(
+            tokens("a 2>&1 >>f <<<w &>g ;;&"),
+            [
+                "a",
+                ">&",
+                "1",
+                ">>",
+                "f",
+                "<<<",
+                "w",
+                "&>",
+                "g",
+                ";;&",
+                "end of file"
+            ]
+        );
+
+        let redirect = &Lexer::new("2>f").tokenize().unwrap()[0];
+        assert_eq!(redirect.kind, TokenKind::Operator(Operator::Great, Some(2)));
+    }
+
+    #[test]
+    fn reads_here_document_bodies() {
+        let tokens = Lexer::new("cat <<EOF; <<-'E'\n$a\nEOF\n\tb\n\tE\necho")
+            .tokenize()
+            .unwrap();
+
+        let bodies: Vec<&TokenKind> = tokens
+            .iter()
+            .map(|token| &token.kind)
+            .filter(|kind| matches!(kind, TokenKind::HereDocument { .. }))
+            .collect();
+
+        assert_eq!(
+            bodies,
+            [
+                &TokenKind::HereDocument {
+                    body: "$a\n".to_string(),
+                    expand: true
+                },
+                &TokenKind::HereDocument {
+                    body: "b\n".to_string(),
+                    expand: false
+                },
+            ]
+        );
+        assert!(tokens[tokens.len() - 2].is_word("echo"));
+    }
+
+    #[test]
+    fn keeps_substitutions_in_one_word() {
+        assert_eq!(
+            tokens("echo a$(echo \"b)\" c)d `x y` $((1 + 2)) ${v:-(}"),
+            [
+                "echo",
+                "a$(echo \"b)\" c)d",
+                "`x y`",
+                "$((1 + 2))",
+                "${v:-(}",
+                "end of file"
+            ]
+        );
+        assert_eq!(
+            tokens("echo $( echo $(echo a) b ) c"),
+            ["echo", "$( echo $(echo a) b )", "c", "end of file"]
+        );
+    }
+
+    #[test]
+    fn ends_substitutions_after_case_patterns_and_comments() {
+        assert_eq!(
+            tokens("echo $(case x in (a) b;; x|y) (c);; esac) d"),
+            [
+                "echo",
+                "$(case x in (a) b;; x|y) (c);; esac)",
+                "d",
+                "end of file"
+            ]
+        );
+        assert_eq!(
+            tokens("a=$(echo hi # not )\n)"),
+            ["a=$(echo hi # not )\n)", "end of file"]
+        );
+        assert_eq!(
+            tokens("$(cat <<E\n)\nE\n)"),
+            ["$(cat <<E\n)\nE\n)", "end of file"]
+        );
+        assert_eq!(substitution_end("echo case; echo esac) x"), Some(20));
+    }
+
+    #[test]
+    fn reports_unterminated_input() {
+        assert_eq!(error("echo 'a"), SyntaxErrorKind::Unterminated('\''));
+        assert_eq!(error("echo \"a"), SyntaxErrorKind::Unterminated('"'));
+        assert_eq!(error("echo `a"), SyntaxErrorKind::Unterminated('`'));
+        assert_eq!(error("echo $(echo a"), SyntaxErrorKind::Unterminated(')'));
+        assert_eq!(
+            error("echo $(case x in x) a;;"),
+            SyntaxErrorKind::Unterminated(')')
+        );
+        assert_eq!(error("echo ${a"), SyntaxErrorKind::Unterminated('}'));
+        assert_eq!(error("echo a\\"), SyntaxErrorKind::UnexpectedEnd);
+        assert_eq!(
+            error("cat <<EOF\nbody"),
+            SyntaxErrorKind::UnterminatedHereDocument("EOF".to_string())
+        );
+        assert_eq!(substitution_end("echo (a"), None);
+    }
+}
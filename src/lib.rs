@@ -1,13 +1,33 @@
+pub mod arithmetic;
+pub mod ast;
 pub mod autocomplete;
+pub mod brace;
 pub mod builtins;
+pub mod expansion;
+pub mod glob;
+pub mod interpreter;
+pub mod jobs;
+pub mod lexer;
 pub mod parser;
 pub mod stream;
 pub mod pipeline;
 pub mod shell;
+pub mod trap;
+pub mod variables;
 
+pub use arithmetic::*;
+pub use ast::*;
 pub use autocomplete::*;
+pub use brace::*;
 pub use builtins::*;
+pub use expansion::*;
+pub use glob::*;
+pub use interpreter::*;
+pub use jobs::*;
+pub use lexer::*;
 pub use parser::*;
 pub use stream::*;
 pub use pipeline::*;
 pub use shell::*;
+pub use trap::*;
+pub use variables::*;
@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::fs;
 use std::io::{self, Read, Write};
 use std::os::unix::io::RawFd;
 use std::process::exit;
@@ -6,12 +7,14 @@ use std::process::exit;
 use termios::{tcsetattr, Termios};
 
 use shell_starter_rust::{
-    autocomplete, bell, parse_argv, prompt, run_pipeline, run_single, AutocompleteResult,
-    RedirectStreams, Shell, ShellCommand,
+    autocomplete, bell, format_job, ignore_signals, parse_argv, prompt, run_list,
+    run_pending_traps, AutocompleteResult, List, ParseResult, Shell, SyntaxError, Terminal,
+    CONTINUATION_PROMPT, PRIMARY_PROMPT, STATUS_NOT_FOUND, STATUS_SIGNAL_BASE, STATUS_SYNTAX_ERROR,
 };
 
 enum ReadResult {
     Quit,
+    Cancel,
     Empty,
     Content(String),
 }
@@ -19,24 +22,33 @@ enum ReadResult {
 #[derive(Parser, Debug)]
 #[command(version)]
 struct Args {
-    #[arg(long, num_args=1..=100)]
-    builtin: Vec<String>,
+    script: Option<String>,
+
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    arguments: Vec<String>,
 }
 
 const UP: u8 = b'A';
 const DOWN: u8 = b'B';
 
-fn change_line(line: &mut String, new_content: &String) {
-    let mut backspaces = String::new();
-    let mut spaces = String::new();
-    for _ in 0..line.len() {
-        backspaces.push('\u{8}');
-        spaces.push(' ');
-    }
+fn change_line(prompt_text: &str, line: &mut String, new_content: &String) {
+    let lines_up = line.matches('\n').count();
+
+    if lines_up != 0 {
+        let column = prompt_text.len();
+        write!(io::stdout(), "\r\x1b[{}A\x1b[{}C\x1b[J", lines_up, column).unwrap();
+    } else {
+        let mut backspaces = String::new();
+        let mut spaces = String::new();
+        for _ in 0..line.len() {
+            backspaces.push('\u{8}');
+            spaces.push(' ');
+        }
 
-    io::stdout().write(backspaces.as_bytes()).unwrap();
-    io::stdout().write(spaces.as_bytes()).unwrap();
-    io::stdout().write(backspaces.as_bytes()).unwrap();
+        io::stdout().write(backspaces.as_bytes()).unwrap();
+        io::stdout().write(spaces.as_bytes()).unwrap();
+        io::stdout().write(backspaces.as_bytes()).unwrap();
+    }
 
     io::stdout().write(new_content.as_bytes()).unwrap();
     io::stdout().flush().unwrap();
@@ -45,8 +57,8 @@ fn change_line(line: &mut String, new_content: &String) {
     line.push_str(new_content);
 }
 
-fn read(shell: &Shell) -> ReadResult {
-    prompt();
+fn read(shell: &Shell, prompt_text: &str) -> ReadResult {
+    prompt(prompt_text);
 
     let stdin_fd: RawFd = 0;
     let previous = Termios::from_fd(stdin_fd).unwrap();
@@ -55,6 +67,7 @@ fn read(shell: &Shell) -> ReadResult {
     new.c_iflag &= termios::IGNCR;
     new.c_lflag ^= termios::ICANON;
     new.c_lflag ^= termios::ECHO;
+    new.c_lflag &= !termios::ISIG;
     new.c_cc[termios::VMIN] = 1;
     new.c_cc[termios::VTIME] = 0;
 
@@ -84,6 +97,14 @@ fn read(shell: &Shell) -> ReadResult {
                 result = ReadResult::Quit;
                 break;
             }
+            '\u{3}' => {
+                io::stdout().write("^C\r\n".as_bytes()).unwrap();
+                io::stdout().flush().unwrap();
+
+                result = ReadResult::Cancel;
+                break;
+            }
+            '\u{1a}' | '\u{1c}' => {}
             '\r' | '\n' => {
                 io::stdout().write("\r\n".as_bytes()).unwrap();
                 io::stdout().flush().unwrap();
@@ -95,7 +116,7 @@ fn read(shell: &Shell) -> ReadResult {
                 };
                 break;
             }
-            '\t' => match autocomplete(shell, &mut line, bell_rang) {
+            '\t' => match autocomplete(shell, prompt_text, &mut line, bell_rang) {
                 AutocompleteResult::None => {
                     bell_rang = false;
                     bell();
@@ -116,14 +137,14 @@ fn read(shell: &Shell) -> ReadResult {
 
                     if direction == UP && history_position != 0 {
                         history_position -= 1;
-                        change_line(&mut line, &shell.history[history_position]);
+                        change_line(prompt_text, &mut line, &shell.history[history_position]);
                     } else if direction == DOWN && history_position < history_len {
                         history_position += 1;
 
                         if history_position == history_len {
-                            change_line(&mut line, &"".to_string());
+                            change_line(prompt_text, &mut line, &"".to_string());
                         } else {
-                            change_line(&mut line, &shell.history[history_position]);
+                            change_line(prompt_text, &mut line, &shell.history[history_position]);
                         }
                     }
                 }
@@ -148,18 +169,16 @@ fn read(shell: &Shell) -> ReadResult {
     return result;
 }
 
-fn eval(shell: &mut Shell, line: String) -> Option<i32> {
-    shell.history.push(line.clone());
+fn parse_line(buffer: &mut String, line: &str) -> Option<ParseResult<List>> {
+    if !buffer.is_empty() {
+        buffer.push('\n');
+    }
 
-    let commands = parse_argv(line);
+    buffer.push_str(line);
 
-    return match commands.len() {
-        0 => None,
-        1 => run_single(shell, &commands[0]),
-        _ => {
-            run_pipeline(shell, commands);
-            None
-        },
+    match parse_argv(buffer) {
+        Err(error) if error.is_incomplete() => None,
+        result => Some(result),
     }
 }
 
@@ -170,36 +189,135 @@ fn main() {
     // shell.history.push("333".to_string());
 
     let args = Args::parse();
-    if !args.builtin.is_empty() {
-        let program = &args.builtin[0];
-        match shell.query(program) {
-            ShellCommand::Builtin(builtin) => {
-                let mut standard = RedirectStreams::standard();
-                builtin(&mut shell, &args.builtin, &mut standard);
-                exit(0);
+
+    let exit_code = match args.script {
+        Some(path) => {
+            shell.name = path.clone();
+            shell.positional_parameters = args.arguments;
+            run_script(&mut shell, &path)
+        }
+        None => repl(&mut shell),
+    };
+
+    let exit_code = shell.finish(exit_code);
+
+    exit(exit_code);
+}
+
+fn run_script(shell: &mut Shell, path: &String) -> i32 {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            return STATUS_NOT_FOUND;
+        }
+    };
+
+    let mut buffer = String::new();
+    let mut first_line = 1;
+
+    for (index, line) in content.lines().enumerate() {
+        let result = match parse_line(&mut buffer, line) {
+            Some(result) => result,
+            None => continue,
+        };
+
+        match result {
+            Ok(list) => {
+                if let Some(code) = run_list(shell, &list) {
+                    return code;
+                }
             }
-            _ => {
-                eprintln!("{}: command not found", program);
-                exit(1);
+            Err(error) => {
+                eprintln!("{}: line {}: {}", path, first_line + error.line - 1, error);
+                return STATUS_SYNTAX_ERROR;
             }
         }
+
+        buffer.clear();
+        first_line = index + 2;
     }
 
-    let mut exit_code = 0;
+    if let Err(error) = parse_argv(&buffer) {
+        eprintln!("{}: line {}: {}", path, first_line + error.line - 1, error);
+        return STATUS_SYNTAX_ERROR;
+    }
+
+    shell.last_status
+}
+
+fn report_syntax_error(shell: &mut Shell, error: &SyntaxError, source: &str) {
+    eprintln!("{}", error);
+    eprintln!("{}", error.caret(source));
+    shell.last_status = STATUS_SYNTAX_ERROR;
+}
+
+fn report_jobs(shell: &mut Shell) {
+    for (job, marker, description) in shell.jobs.update() {
+        eprintln!("{}", format_job(&job, marker, &description));
+    }
+}
+
+fn repl(shell: &mut Shell) -> i32 {
+    let mut buffer = String::new();
+    shell.interactive = true;
+    shell.terminal = Terminal::acquire();
+    ignore_signals();
+
     loop {
-        match read(&shell) {
-            ReadResult::Quit => break,
-            ReadResult::Empty => continue,
-            ReadResult::Content(line) => {
-                if let Some(code) = eval(&mut shell, line) {
-                    exit_code = code;
-                    break;
+        let prompt_text = if buffer.is_empty() {
+            report_jobs(shell);
+
+            if let Some(code) = run_pending_traps(shell) {
+                return code;
+            }
+
+            PRIMARY_PROMPT.to_string()
+        } else {
+            shell
+                .variables
+                .get("PS2")
+                .cloned()
+                .unwrap_or(CONTINUATION_PROMPT.into())
+        };
+
+        let line = match read(shell, &prompt_text) {
+            ReadResult::Quit if buffer.is_empty() => return shell.last_status,
+            ReadResult::Quit => {
+                println!();
+
+                if let Err(error) = parse_argv(&buffer) {
+                    report_syntax_error(shell, &error, &buffer);
                 }
-            },
+
+                buffer.clear();
+                continue;
+            }
+            ReadResult::Cancel => {
+                buffer.clear();
+                shell.last_status = STATUS_SIGNAL_BASE + libc::SIGINT;
+                continue;
+            }
+            ReadResult::Empty if buffer.is_empty() => continue,
+            ReadResult::Empty => String::new(),
+            ReadResult::Content(line) => line,
+        };
+
+        let result = match parse_line(&mut buffer, &line) {
+            Some(result) => result,
+            None => continue,
+        };
+
+        let source = std::mem::take(&mut buffer);
+        shell.history.push(source.clone());
+
+        match result {
+            Ok(list) => {
+                if let Some(code) = run_list(shell, &list) {
+                    return code;
+                }
+            }
+            Err(error) => report_syntax_error(shell, &error, &source),
         }
     }
-
-    shell.finish();
-
-    exit(exit_code);
 }
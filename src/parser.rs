@@ -1,171 +1,798 @@
-pub use core::str::Chars;
-use std::iter::Peekable;
-
-const SPACE: char = ' ';
-const SINGLE: char = '\'';
-const DOUBLE: char = '"';
-const BACKSLASH: char = '\\';
-const GREATER_THAN: char = '>';
-const PIPE: char = '|';
-
-#[derive(Debug, Clone)]
-pub enum StandardNamedStream {
-    Unknown = -1,
-    Output = 1,
-    Error = 2,
+use thiserror::Error;
+
+use crate::{
+    is_valid_name, AndOrList, ArithmeticForClause, Assignment, CaseClause, CaseItem,
+    CaseTerminator, Command, CompoundCommand, Connector, ForClause, FunctionDefinition, IfClause,
+    Lexer, List, LoopClause, Operator, Pipeline, Redirect, RedirectTarget, SimpleCommand, Token,
+    TokenKind,
+};
+
+/// Reserved words that close the list before them, when found in command position.
+const CLOSING_WORDS: [&str; 8] = ["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SyntaxErrorKind {
+    #[error("syntax error near unexpected token `{0}'")]
+    UnexpectedToken(String),
+    #[error("syntax error: unexpected end of file")]
+    UnexpectedEnd,
+    #[error("unexpected EOF while looking for matching `{0}'")]
+    Unterminated(char),
+    #[error("here-document delimited by end-of-file (wanted `{0}')")]
+    UnterminatedHereDocument(String),
 }
 
-impl From<u32> for StandardNamedStream {
-    fn from(fd: u32) -> Self {
-        match fd {
-            1 => Self::Output,
-            2 => Self::Error,
-            _ => Self::Unknown,
+#[derive(Debug, Clone, Error)]
+#[error("{kind}")]
+pub struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SyntaxError {
+    pub fn new(kind: SyntaxErrorKind, source: &str, offset: usize) -> SyntaxError {
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+        SyntaxError {
+            kind,
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
         }
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Redirect {
-    pub stream_name: StandardNamedStream,
-    pub path: String,
-    pub append: bool,
-}
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self.kind,
+            SyntaxErrorKind::UnexpectedEnd
+                | SyntaxErrorKind::Unterminated(_)
+                | SyntaxErrorKind::UnterminatedHereDocument(_)
+        )
+    }
 
-pub struct ParsedLine {
-    pub arguments: Vec<String>,
-    pub redirects: Vec<Redirect>,
+    pub fn caret(&self, source: &str) -> String {
+        let line = source.lines().nth(self.line - 1).unwrap_or("");
+
+        format!("{}\n{}^", line, " ".repeat(self.column - 1))
+    }
 }
 
-struct LineParser<'a> {
-    chars: Peekable<Chars<'a>>,
-    commands: Vec<ParsedLine>,
-    arguments: Vec<String>,
-    redirects: Vec<Redirect>,
+pub type ParseResult<T> = Result<T, SyntaxError>;
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    index: usize,
 }
 
-impl<'a> LineParser<'a> {
-    fn new(line: &'a String) -> LineParser<'a> {
-        LineParser {
-            chars: line.chars().peekable(),
-            commands: Vec::new(),
-            arguments: Vec::new(),
-            redirects: Vec::new(),
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> ParseResult<Parser<'a>> {
+        Ok(Parser {
+            source,
+            tokens: Lexer::new(source).tokenize()?,
+            index: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, offset: usize) -> &Token {
+        let index = (self.index + offset).min(self.tokens.len() - 1);
+
+        &self.tokens[index]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+
+        if self.index < self.tokens.len() - 1 {
+            self.index += 1;
+        }
+
+        token
+    }
+
+    /// The source text from `start` up to the end of the last consumed token.
+    fn text_since(&self, start: usize) -> String {
+        let end = match self.index.checked_sub(1) {
+            Some(index) => self.tokens[index].end,
+            None => start,
+        };
+
+        self.source[start..end.max(start)].to_string()
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek().kind == TokenKind::Newline {
+            self.advance();
+        }
+    }
+
+    fn unexpected(&self) -> SyntaxError {
+        let token = self.peek();
+
+        let kind = match token.kind {
+            TokenKind::End => SyntaxErrorKind::UnexpectedEnd,
+            _ => SyntaxErrorKind::UnexpectedToken(token.as_str().into()),
+        };
+
+        SyntaxError::new(kind, self.source, token.offset)
+    }
+
+    fn unexpected_token(&self) -> SyntaxError {
+        let token = self.peek();
+
+        let kind = match token.kind {
+            TokenKind::End => SyntaxErrorKind::UnexpectedToken("newline".into()),
+            _ => SyntaxErrorKind::UnexpectedToken(token.as_str().into()),
+        };
+
+        SyntaxError::new(kind, self.source, token.offset)
+    }
+
+    fn expect_operator(&mut self, operator: Operator) -> ParseResult<()> {
+        if !self.peek().is_operator(operator) {
+            return Err(self.unexpected());
         }
+
+        self.advance();
+        Ok(())
     }
 
-    fn parse(mut self) -> Vec<ParsedLine> {
-        while let Some(argument) = self.next_argument() {
-            self.arguments.push(argument);
+    fn expect_word(&mut self, word: &str) -> ParseResult<()> {
+        if !self.peek().is_word(word) {
+            return Err(self.unexpected());
         }
 
-        if !self.arguments.is_empty() {
-            self.pipe();
+        self.advance();
+        Ok(())
+    }
+
+    fn parse_program(mut self) -> ParseResult<List> {
+        let list = self.parse_list()?;
+
+        if self.peek().kind != TokenKind::End {
+            return Err(self.unexpected());
         }
 
-        return self.commands;
+        Ok(list)
     }
 
-    fn next_argument(&mut self) -> Option<String> {
-        let mut builder: Vec<char> = Vec::new();
+    fn is_list_end(&self) -> bool {
+        let token = self.peek();
 
-        while let Some(character) = self.chars.next() {
-            match character {
-                SPACE => {
-                    if !builder.is_empty() {
-                        let argument: String = builder.iter().collect();
+        match &token.kind {
+            TokenKind::End => true,
+            TokenKind::Operator(Operator::RightParenthesis, _) => true,
+            TokenKind::Operator(operator, _) => operator.is_case_terminator(),
+            TokenKind::Word(word) => CLOSING_WORDS.contains(&word.as_str()),
+            _ => false,
+        }
+    }
 
-                        return Some(argument);
-                    }
+    fn parse_list(&mut self) -> ParseResult<List> {
+        let mut list = List::default();
+
+        loop {
+            self.skip_newlines();
+
+            if self.is_list_end() {
+                break;
+            }
+
+            let mut and_or = self.parse_and_or()?;
+
+            let separated = match self.peek().kind {
+                TokenKind::Operator(Operator::Semicolon, _) | TokenKind::Newline => true,
+                TokenKind::Operator(Operator::Ampersand, _) => {
+                    and_or.background = true;
+                    true
                 }
-                SINGLE => {
-                    while let Some(character) = self.chars.next() {
-                        if character == SINGLE {
-                            break;
-                        }
+                _ => false,
+            };
 
-                        builder.push(character);
-                    }
+            list.items.push(and_or);
+
+            if !separated {
+                break;
+            }
+
+            self.advance();
+        }
+
+        Ok(list)
+    }
+
+    fn parse_body(&mut self) -> ParseResult<List> {
+        let list = self.parse_list()?;
+
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+
+        Ok(list)
+    }
+
+    fn parse_do_group(&mut self) -> ParseResult<List> {
+        self.expect_word("do")?;
+        let body = self.parse_body()?;
+        self.expect_word("done")?;
+
+        Ok(body)
+    }
+
+    fn parse_and_or(&mut self) -> ParseResult<AndOrList> {
+        let start = self.peek().offset;
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+
+        loop {
+            let connector = match self.peek().kind {
+                TokenKind::Operator(Operator::And, _) => Connector::And,
+                TokenKind::Operator(Operator::Or, _) => Connector::Or,
+                _ => break,
+            };
+
+            self.advance();
+            self.skip_newlines();
+
+            rest.push((connector, self.parse_pipeline()?));
+        }
+
+        Ok(AndOrList {
+            first,
+            rest,
+            background: false,
+            text: self.text_since(start),
+        })
+    }
+
+    fn parse_pipeline(&mut self) -> ParseResult<Pipeline> {
+        let start = self.peek().offset;
+        let mut pipeline = Pipeline::default();
+
+        if self.peek().is_word("!") {
+            self.advance();
+            pipeline.negated = true;
+        }
+
+        pipeline.commands.push(self.parse_command()?);
+
+        while self.peek().is_operator(Operator::Pipe) {
+            self.advance();
+            self.skip_newlines();
+
+            pipeline.commands.push(self.parse_command()?);
+        }
+
+        pipeline.text = self.text_since(start);
+        Ok(pipeline)
+    }
+
+    fn parse_command(&mut self) -> ParseResult<Command> {
+        let token = self.peek();
+
+        if token.is_word("function") {
+            return self.parse_function_definition(true);
+        }
+
+        if let TokenKind::Word(word) = &token.kind {
+            if is_valid_name(word) && self.peek_nth(1).is_operator(Operator::LeftParenthesis) {
+                return self.parse_function_definition(false);
+            }
+        }
+
+        if let Some(compound) = self.parse_compound_command()? {
+            let redirects = self.parse_redirects()?;
+
+            return Ok(Command::Compound(compound, redirects));
+        }
+
+        self.parse_simple_command().map(Command::Simple)
+    }
+
+    fn parse_compound_command(&mut self) -> ParseResult<Option<CompoundCommand>> {
+        let token = self.peek();
+
+        if let TokenKind::Arithmetic(expression) = &token.kind {
+            let expression = expression.clone();
+            self.advance();
+
+            return Ok(Some(CompoundCommand::Arithmetic(expression)));
+        }
+
+        if token.is_word("{") {
+            self.advance();
+            let list = self.parse_list()?;
+            self.expect_word("}")?;
+
+            return Ok(Some(CompoundCommand::BraceGroup(list)));
+        }
+
+        if token.is_operator(Operator::LeftParenthesis) {
+            self.advance();
+            let list = self.parse_list()?;
+            self.expect_operator(Operator::RightParenthesis)?;
+
+            return Ok(Some(CompoundCommand::Subshell(list)));
+        }
+
+        let compound = match &token.kind {
+            TokenKind::Word(word) => match word.as_str() {
+                "if" => self.parse_if()?,
+                "while" => self.parse_loop(false)?,
+                "until" => self.parse_loop(true)?,
+                "for" => self.parse_for()?,
+                "case" => self.parse_case()?,
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        Ok(Some(compound))
+    }
+
+    fn parse_if(&mut self) -> ParseResult<CompoundCommand> {
+        let mut branches = Vec::new();
+
+        loop {
+            self.advance();
+
+            let condition = self.parse_body()?;
+            self.expect_word("then")?;
+            let body = self.parse_body()?;
+
+            branches.push((condition, body));
+
+            if !self.peek().is_word("elif") {
+                break;
+            }
+        }
+
+        let otherwise = if self.peek().is_word("else") {
+            self.advance();
+            Some(self.parse_body()?)
+        } else {
+            None
+        };
+
+        self.expect_word("fi")?;
+
+        Ok(CompoundCommand::If(IfClause {
+            branches,
+            otherwise,
+        }))
+    }
+
+    fn parse_loop(&mut self, until: bool) -> ParseResult<CompoundCommand> {
+        self.advance();
+
+        let condition = self.parse_body()?;
+        let body = self.parse_do_group()?;
+
+        Ok(CompoundCommand::Loop(LoopClause {
+            condition,
+            body,
+            until,
+        }))
+    }
+
+    fn parse_for(&mut self) -> ParseResult<CompoundCommand> {
+        self.advance();
+
+        if let TokenKind::Arithmetic(expression) = &self.peek().kind {
+            let parts: Vec<String> = expression.split(';').map(String::from).collect();
+            let [initialize, condition, update] =
+                <[String; 3]>::try_from(parts).map_err(|_| self.unexpected_token())?;
+            self.advance();
+
+            if self.peek().is_operator(Operator::Semicolon) {
+                self.advance();
+            }
+            self.skip_newlines();
+
+            return Ok(CompoundCommand::ArithmeticFor(ArithmeticForClause {
+                initialize,
+                condition,
+                update,
+                body: self.parse_do_group()?,
+            }));
+        }
+
+        let name = match &self.peek().kind {
+            TokenKind::Word(name) if is_valid_name(name) => name.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+        self.skip_newlines();
+
+        let words = if self.peek().is_word("in") {
+            self.advance();
+
+            let mut words = Vec::new();
+            while let TokenKind::Word(word) = &self.peek().kind {
+                words.push(word.clone());
+                self.advance();
+            }
+
+            match self.peek().kind {
+                TokenKind::Operator(Operator::Semicolon, _) | TokenKind::Newline => {
+                    self.advance();
                 }
-                DOUBLE => {
-                    while let Some(character) = self.chars.next() {
-                        match character {
-                            DOUBLE => break,
-                            BACKSLASH => self.backslash(&mut builder, true),
-                            _ => builder.push(character),
-                        }
-                    }
+                _ => return Err(self.unexpected()),
+            }
+
+            Some(words)
+        } else {
+            if self.peek().is_operator(Operator::Semicolon) {
+                self.advance();
+            }
+
+            None
+        };
+
+        self.skip_newlines();
+
+        Ok(CompoundCommand::For(ForClause {
+            name,
+            words,
+            body: self.parse_do_group()?,
+        }))
+    }
+
+    fn parse_case(&mut self) -> ParseResult<CompoundCommand> {
+        self.advance();
+
+        let word = match &self.peek().kind {
+            TokenKind::Word(word) => word.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+        self.skip_newlines();
+        self.expect_word("in")?;
+        self.skip_newlines();
+
+        let mut items = Vec::new();
+
+        while !self.peek().is_word("esac") {
+            if self.peek().is_operator(Operator::LeftParenthesis) {
+                self.advance();
+            }
+
+            let mut patterns = Vec::new();
+            loop {
+                match &self.peek().kind {
+                    TokenKind::Word(pattern) => patterns.push(pattern.clone()),
+                    _ => return Err(self.unexpected()),
                 }
-                BACKSLASH => self.backslash(&mut builder, false),
-                GREATER_THAN => self.redirect(StandardNamedStream::Output),
-                PIPE => self.pipe(),
-                _ => {
-                    if character.is_digit(10) && self.chars.peek() == Some(&GREATER_THAN) {
-                        self.chars.next().unwrap();
-                        self.redirect(character.to_digit(10).unwrap().into());
-                    } else {
-                        builder.push(character)
-                    }
+                self.advance();
+
+                if !self.peek().is_operator(Operator::Pipe) {
+                    break;
                 }
+                self.advance();
+            }
+
+            self.expect_operator(Operator::RightParenthesis)?;
+            let body = self.parse_list()?;
+
+            let terminator = match self.peek().kind {
+                TokenKind::Operator(Operator::DoubleSemicolon, _) => Some(CaseTerminator::Break),
+                TokenKind::Operator(Operator::SemicolonAnd, _) => Some(CaseTerminator::FallThrough),
+                TokenKind::Operator(Operator::DoubleSemicolonAnd, _) => {
+                    Some(CaseTerminator::Continue)
+                }
+                _ => None,
+            };
+
+            items.push(CaseItem {
+                patterns,
+                body,
+                terminator: terminator.unwrap_or(CaseTerminator::Break),
+            });
+
+            if terminator.is_none() {
+                break;
             }
+
+            self.advance();
+            self.skip_newlines();
         }
 
-        if !builder.is_empty() {
-            let argument: String = builder.iter().collect();
+        self.expect_word("esac")?;
+
+        Ok(CompoundCommand::Case(CaseClause { word, items }))
+    }
 
-            return Some(argument);
+    fn parse_function_definition(&mut self, keyword: bool) -> ParseResult<Command> {
+        if keyword {
+            self.advance();
         }
 
-        return None;
+        let name = match &self.peek().kind {
+            TokenKind::Word(name) if is_valid_name(name) => name.clone(),
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+
+        if !keyword || self.peek().is_operator(Operator::LeftParenthesis) {
+            self.advance();
+
+            if !self.peek().is_operator(Operator::RightParenthesis) {
+                return Err(self.unexpected_token());
+            }
+
+            self.advance();
+        }
+
+        self.skip_newlines();
+
+        let body = match self.parse_compound_command()? {
+            Some(compound) => Command::Compound(compound, self.parse_redirects()?),
+            None => return Err(self.unexpected()),
+        };
+
+        Ok(Command::FunctionDefinition(FunctionDefinition {
+            name,
+            body: Box::new(body),
+        }))
+    }
+
+    fn parse_redirects(&mut self) -> ParseResult<Vec<Redirect>> {
+        let mut redirects = Vec::new();
+
+        while self.parse_redirect(&mut redirects)? {}
+
+        Ok(redirects)
     }
 
-    fn backslash(&mut self, builder: &mut Vec<char>, in_quote: bool) {
-        if let Some(mut character) = self.chars.next() {
-            if in_quote {
-                if let Some(mapped) = LineParser::map_backslash_character(character) {
-                    character = mapped;
-                } else {
-                    builder.push(BACKSLASH);
+    fn parse_redirect(&mut self, redirects: &mut Vec<Redirect>) -> ParseResult<bool> {
+        let (operator, fd) = match self.peek().kind {
+            TokenKind::Operator(operator, fd) if operator.is_redirection() => (operator, fd),
+            _ => return Ok(false),
+        };
+        self.advance();
+
+        let target = match (&self.peek().kind, operator) {
+            (TokenKind::HereDocument { body, expand }, _) => RedirectTarget::HereDocument {
+                body: body.clone(),
+                expand: *expand,
+            },
+            (TokenKind::Word(word), Operator::TripleLess) => {
+                RedirectTarget::HereString { word: word.clone() }
+            }
+            (TokenKind::Word(path), Operator::Less) => RedirectTarget::Input { path: path.clone() },
+            (TokenKind::Word(path), Operator::LessGreat) => {
+                RedirectTarget::ReadWrite { path: path.clone() }
+            }
+            (TokenKind::Word(path), Operator::Great | Operator::DoubleGreat) => {
+                RedirectTarget::Output {
+                    path: path.clone(),
+                    append: operator == Operator::DoubleGreat,
+                }
+            }
+            (TokenKind::Word(path), Operator::AndGreat | Operator::AndDoubleGreat) => {
+                RedirectTarget::Output {
+                    path: path.clone(),
+                    append: operator == Operator::AndDoubleGreat,
                 }
             }
+            (TokenKind::Word(word), Operator::GreatAnd) if fd.is_none() && !is_duplicable(word) => {
+                RedirectTarget::Output {
+                    path: word.clone(),
+                    append: false,
+                }
+            }
+            (TokenKind::Word(word), Operator::LessAnd | Operator::GreatAnd) => {
+                RedirectTarget::Duplicate { word: word.clone() }
+            }
+            _ => return Err(self.unexpected_token()),
+        };
+        self.advance();
+
+        let default_fd = match operator {
+            Operator::Great
+            | Operator::DoubleGreat
+            | Operator::GreatAnd
+            | Operator::AndGreat
+            | Operator::AndDoubleGreat => 1,
+            _ => 0,
+        };
+
+        let both = matches!(
+            (operator, &target),
+            (Operator::AndGreat | Operator::AndDoubleGreat, _)
+                | (Operator::GreatAnd, RedirectTarget::Output { .. })
+        );
+
+        redirects.push(Redirect {
+            fd: fd.unwrap_or(default_fd),
+            target,
+        });
 
-            builder.push(character);
+        if both {
+            redirects.push(Redirect {
+                fd: 2,
+                target: RedirectTarget::Duplicate { word: "1".into() },
+            });
         }
+
+        Ok(true)
     }
 
-    fn map_backslash_character(character: char) -> Option<char> {
-        match character {
-            BACKSLASH | DOUBLE => Some(character),
-            _ => None,
+    fn parse_simple_command(&mut self) -> ParseResult<SimpleCommand> {
+        let mut command = SimpleCommand::default();
+
+        loop {
+            if self.parse_redirect(&mut command.redirects)? {
+                continue;
+            }
+
+            let word = match &self.peek().kind {
+                TokenKind::Word(word) => word.clone(),
+                _ => break,
+            };
+            self.advance();
+
+            if command.arguments.is_empty() {
+                if let Some((name, value)) = word.split_once('=') {
+                    if is_valid_name(name) {
+                        command.assignments.push(Assignment {
+                            name: name.to_string(),
+                            value: value.to_string(),
+                        });
+
+                        continue;
+                    }
+                }
+            }
+
+            command.arguments.push(word);
         }
+
+        if command.arguments.is_empty()
+            && command.assignments.is_empty()
+            && command.redirects.is_empty()
+        {
+            return Err(self.unexpected());
+        }
+
+        Ok(command)
     }
+}
 
-    fn redirect(&mut self, stream_name: StandardNamedStream) {
-        let append = self.chars.peek() == Some(&GREATER_THAN);
-        if append {
-            self.chars.next();
+fn is_duplicable(word: &str) -> bool {
+    word == "-" || word.contains('$') || word.chars().all(|c| c.is_ascii_digit())
+}
+
+pub fn parse_argv(line: &str) -> ParseResult<List> {
+    Parser::new(line)?.parse_program()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_commands(line: &str) -> Vec<Command> {
+        let list = parse_argv(line).unwrap();
+        assert_eq!(list.items.len(), 1);
+
+        list.items[0].first.commands.clone()
+    }
+
+    fn arguments(command: &Command) -> Vec<String> {
+        match command {
+            Command::Simple(simple) => simple.arguments.clone(),
+            _ => panic!("not a simple command: {:?}", command),
         }
+    }
 
-        let path = self.next_argument().unwrap_or("".into());
+    fn parse_error(line: &str) -> SyntaxError {
+        parse_argv(line).unwrap_err()
+    }
 
-        self.redirects.push(Redirect {
-            stream_name,
-            path,
-            append,
-        });
+    #[test]
+    fn parses_lists_and_pipelines() {
+        let list = parse_argv("a | b && ! c || d; e &").unwrap();
+        assert_eq!(list.items.len(), 2);
+
+        let first = &list.items[0];
+        assert_eq!(first.first.commands.len(), 2);
+        assert!(!first.background);
+
+        let connectors: Vec<Connector> =
+            first.rest.iter().map(|(connector, _)| *connector).collect();
+        assert_eq!(connectors, [Connector::And, Connector::Or]);
+        assert!(first.rest[0].1.negated);
+
+        assert!(list.items[1].background);
+        assert_eq!(list.items[1].text, "e");
     }
 
-    fn pipe(&mut self) {
-        self.commands.push(ParsedLine {
-            arguments: self.arguments.clone(),
-            redirects: self.redirects.clone(),
-        });
+    #[test]
+    fn parses_assignments_and_redirects() {
+        let commands = parse_commands("A=1 B=2 cmd x 2>&1 <in");
+        let Command::Simple(simple) = &commands[0] else {
+            panic!("not a simple command");
+        };
 
-        self.arguments.clear();
-        self.redirects.clear();
+        let names: Vec<&str> = simple.assignments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["A", "B"]);
+        assert_eq!(simple.arguments, ["cmd", "x"]);
+        assert_eq!(simple.redirects.len(), 2);
+        assert_eq!(simple.redirects[0].fd, 2);
+        assert!(matches!(
+            &simple.redirects[1].target,
+            RedirectTarget::Input { path } if path == "in"
+        ));
     }
-}
 
-pub fn parse_argv(line: String) -> Vec<ParsedLine> {
-    LineParser::new(&line).parse()
+    #[test]
+    fn parses_compound_commands() {
+        let commands = parse_commands("case $x in a|b) c;; (d) e;& esac");
+        let Command::Compound(CompoundCommand::Case(clause), _) = &commands[0] else {
+            panic!("not a case command");
+        };
+
+        assert_eq!(clause.word, "$x");
+        assert_eq!(clause.items[0].patterns, ["a", "b"]);
+        assert_eq!(clause.items[1].patterns, ["d"]);
+        assert_eq!(clause.items[1].terminator, CaseTerminator::FallThrough);
+
+        assert!(matches!(
+            &parse_commands("f() { g; }")[0],
+            Command::FunctionDefinition(definition) if definition.name == "f"
+        ));
+        assert!(matches!(
+            &parse_commands("if a; then b; elif c; then d; else e; fi >out")[0],
+            Command::Compound(CompoundCommand::If(clause), redirects)
+                if clause.branches.len() == 2 && clause.otherwise.is_some() && redirects.len() == 1
+        ));
+    }
+
+    #[test]
+    fn keeps_substitutions_as_arguments() {
+        assert_eq!(
+            arguments(&parse_commands("echo $(case x in x) echo y;; esac) `a b`")[0]),
+            ["echo", "$(case x in x) echo y;; esac)", "`a b`"]
+        );
+    }
+
+    #[test]
+    fn reports_unexpected_tokens_with_positions() {
+        let error = parse_error("echo a | | b");
+        assert_eq!(
+            error.kind,
+            SyntaxErrorKind::UnexpectedToken("|".to_string())
+        );
+        assert_eq!((error.line, error.column), (1, 10));
+        assert_eq!(error.caret("echo a | | b"), "echo a | | b\n         ^");
+
+        let error = parse_error("true\nif then");
+        assert_eq!(
+            error.kind,
+            SyntaxErrorKind::UnexpectedToken("then".to_string())
+        );
+        assert_eq!((error.line, error.column), (2, 4));
+        assert!(!error.is_incomplete());
+    }
+
+    #[test]
+    fn reports_incomplete_input() {
+        for line in [
+            "if a; then b",
+            "a |",
+            "a &&",
+            "f() {",
+            "echo $(echo",
+            "case x in",
+        ] {
+            assert!(parse_error(line).is_incomplete(), "{}", line);
+        }
+    }
 }
@@ -1,26 +1,122 @@
 use std::{
-    env::current_exe,
     fs::File,
-    process::{exit, Child, Command, Stdio},
+    io::{self, Read, Write},
+    process::{exit, Command, ExitStatus},
 };
 
 #[cfg(unix)]
-use std::os::unix::process::CommandExt;
+use std::os::unix::{
+    io::AsRawFd,
+    process::{CommandExt, ExitStatusExt},
+};
+
+use crate::{
+    ast, describe_error, expand_simple_command, fork_job, pipe, reset_signals, run_command,
+    run_function, run_trap, run_with_streams, wait_foreground, Assignment, BuiltinFunction,
+    BuiltinResult, Descriptor, RedirectStreams, Shell, ShellCommand, SimpleCommand, TrapCondition,
+};
+use fork::{fork, Fork};
+
+pub const STATUS_SYNTAX_ERROR: i32 = 2;
+pub const STATUS_NOT_EXECUTABLE: i32 = 126;
+pub const STATUS_NOT_FOUND: i32 = 127;
+pub const STATUS_SIGNAL_BASE: i32 = 128;
+
+pub fn status_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+
+    #[cfg(unix)]
+    if let Some(signal) = status.signal() {
+        return STATUS_SIGNAL_BASE + signal;
+    }
+
+    1
+}
+
+pub fn wait_for(pid: i32) -> i32 {
+    let mut status = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+        return 1;
+    }
+
+    status_code(ExitStatus::from_raw(status))
+}
+
+fn assign(shell: &mut Shell, assignments: &[Assignment]) -> i32 {
+    for assignment in assignments {
+        if let Err(message) = shell
+            .variables
+            .set(&assignment.name, assignment.value.clone())
+        {
+            eprintln!("{}", message);
+            return 1;
+        }
+    }
+
+    0
+}
+
+fn environment(assignments: &[Assignment]) -> impl Iterator<Item = (&String, &String)> {
+    assignments
+        .iter()
+        .map(|assignment| (&assignment.name, &assignment.value))
+}
 
-use crate::{ParsedLine, RedirectStreams, Shell, ShellCommand};
-use fork::{fork, waitpid, Fork};
+pub fn run_single(shell: &mut Shell, parsed_line: &SimpleCommand) -> Option<i32> {
+    let traced = shell.function_depth == 0 && !shell.traps.running;
+    if traced && shell.traps.get(TrapCondition::Debug).is_some() {
+        let words = parsed_line
+            .assignments
+            .iter()
+            .map(|assignment| format!("{}={}", assignment.name, assignment.value))
+            .chain(parsed_line.arguments.iter().cloned());
+
+        let _ = shell
+            .variables
+            .set("BASH_COMMAND", words.collect::<Vec<_>>().join(" "));
+
+        if let Some(code) = run_trap(shell, TrapCondition::Debug) {
+            return Some(code);
+        }
+    }
+
+    shell.substitution_status = None;
+
+    let parsed_line = &match expand_simple_command(shell, parsed_line) {
+        Ok(parsed_line) => parsed_line,
+        Err(message) => return shell.expansion_error(&message),
+    };
+
+    let mut redirected_streams = match RedirectStreams::new(&parsed_line.redirects) {
+        Ok(redirected_streams) => redirected_streams,
+        Err(message) => {
+            eprintln!("{}", message);
+            shell.last_status = 1;
+            return None;
+        }
+    };
 
-pub fn run_single(shell: &mut Shell, parsed_line: &ParsedLine) -> Option<i32> {
     let arguments = &parsed_line.arguments;
     if arguments.is_empty() {
+        shell.last_status = match assign(shell, &parsed_line.assignments) {
+            0 => shell.substitution_status.unwrap_or(0),
+            code => code,
+        };
         return None;
     }
 
-    let mut redirected_streams = RedirectStreams::new(&parsed_line.redirects).unwrap();
-
     let program = &arguments[0];
-    return match shell.query(program) {
-        ShellCommand::Builtin(builtin) => builtin(shell, &arguments, &mut redirected_streams),
+    match shell.query(program) {
+        ShellCommand::Function(body) => {
+            return run_with_streams(shell, redirected_streams, |shell| {
+                run_function(shell, &body, arguments, &parsed_line.assignments)
+            });
+        }
+        ShellCommand::Builtin(builtin) => {
+            return run_builtin(shell, builtin, arguments, &mut redirected_streams);
+        }
         ShellCommand::Executable(path) => {
             let mut command = Command::new(path);
 
@@ -29,78 +125,213 @@ pub fn run_single(shell: &mut Shell, parsed_line: &ParsedLine) -> Option<i32> {
 
             command
                 .args(&arguments[1..])
-                .stdin(Stdio::inherit())
-                .stdout(if let Some(file) = redirected_streams.output {
-                    From::<File>::from(file)
-                } else {
-                    Stdio::inherit()
-                })
-                .stderr(if let Some(file) = redirected_streams.error {
-                    From::<File>::from(file)
-                } else {
-                    Stdio::inherit()
-                })
-                .output()
-                .expect("failed to execute process");
-
-            None
+                .env_clear()
+                .envs(shell.variables.exported())
+                .envs(environment(&parsed_line.assignments));
+
+            if shell.interactive {
+                let terminal = shell.terminal;
+                let traps = shell.traps.clone();
+                let setup = move || {
+                    if let Some(terminal) = terminal {
+                        terminal.enter_group(true);
+                    }
+
+                    reset_signals(&traps);
+                    Ok(())
+                };
+
+                unsafe { command.pre_exec(setup) };
+            }
+
+            // after taking the terminal, as a redirection may replace the descriptor it goes through
+            redirected_streams.configure(&mut command);
+
+            shell.last_status = match command.spawn() {
+                Ok(child) => wait_foreground(shell, child.id() as i32, &arguments.join(" ")),
+                Err(error) => {
+                    eprintln!("{}: {}", program, describe_error(&error));
+                    STATUS_NOT_EXECUTABLE
+                }
+            };
         }
         ShellCommand::None => {
             eprintln!("{}: command not found", program);
-            None
-        },
+            shell.last_status = STATUS_NOT_FOUND;
+        }
+    }
+
+    None
+}
+
+fn run_builtin(
+    shell: &mut Shell,
+    builtin: BuiltinFunction,
+    arguments: &[String],
+    redirected_streams: &mut RedirectStreams,
+) -> Option<i32> {
+    match builtin(shell, arguments, redirected_streams) {
+        BuiltinResult::Status(code) => shell.last_status = code,
+        BuiltinResult::Exit(code) => return Some(code),
     }
+
+    None
 }
 
-pub fn run_pipeline(shell: &mut Shell, commands: Vec<ParsedLine>) {
+pub fn run_pipeline(shell: &mut Shell, pipeline: &ast::Pipeline) {
+    let (mut statuses, report) = match pipe() {
+        Ok(ends) => ends,
+        Err(error) => {
+            eprintln!("pipe: {}", describe_error(&error));
+            shell.last_status = 1;
+            return;
+        }
+    };
+
+    match fork_job(shell, true) {
+        Fork::Parent(child) => {
+            drop(report);
+            shell.last_status = wait_foreground(shell, child, &pipeline.text);
+
+            let reported = match shell.jobs.get_by_pid(child) {
+                Some(_) => None,
+                None => read_statuses(&mut statuses),
+            };
+
+            let stages = pipeline.commands.len();
+            shell.pipe_status = reported.unwrap_or_else(|| vec![shell.last_status; stages]);
+        }
+        Fork::Child => {
+            drop(statuses);
+            _do_run_pipeline(shell, &pipeline.commands, report);
+        }
+    }
+}
+
+/// Reads the status of every stage, as reported by the process that ran the pipeline before exiting.
+fn read_statuses(statuses: &mut File) -> Option<Vec<i32>> {
+    unsafe { libc::fcntl(statuses.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+
+    let mut buffer = [0u8; 4096];
+    let length = statuses.read(&mut buffer).ok()?;
+
+    let reported: Option<Vec<i32>> = String::from_utf8_lossy(&buffer[..length])
+        .split_whitespace()
+        .map(|status| status.parse().ok())
+        .collect();
+
+    reported.filter(|statuses| !statuses.is_empty())
+}
+
+/// Runs a pipeline stage in a forked copy of the shell, returning the child's pid.
+fn fork_stage(
+    shell: &mut Shell,
+    redirected_streams: RedirectStreams,
+    next_read: &mut Option<File>,
+    run: impl FnOnce(&mut Shell) -> Option<i32>,
+) -> i32 {
     match fork().unwrap() {
-        Fork::Parent(child) => waitpid(child).unwrap(),
-        Fork::Child => _do_run_pipeline(shell, commands),
+        Fork::Parent(child) => child,
+        Fork::Child => {
+            drop(next_read.take());
+            unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+            let code = run_with_streams(shell, redirected_streams, run);
+            let _ = io::stdout().flush();
+
+            exit(code.unwrap_or(shell.last_status));
+        }
     }
 }
 
-fn _do_run_pipeline(shell: &mut Shell, parsed_lines: Vec<ParsedLine>) {
-    let mut childs: Vec<Box<Child>> = Vec::new();
+/// A pipeline stage, either still running in a child process or already done.
+enum Stage {
+    Running(i32),
+    Done(i32),
+}
 
-    for (index, parsed_line) in parsed_lines.iter().enumerate() {
-        let redirected_streams = RedirectStreams::new(&parsed_line.redirects).unwrap();
+fn _do_run_pipeline(shell: &mut Shell, commands: &[ast::Command], mut report: File) -> ! {
+    let mut stages: Vec<Stage> = Vec::new();
+    let mut previous_read: Option<File> = None;
 
-        let is_first = index == 0;
-        let is_last = index == parsed_lines.len() - 1;
+    for (index, command) in commands.iter().enumerate() {
+        let is_last = index == commands.len() - 1;
 
-        let program = &parsed_line.arguments[0];
-        match shell.query(program) {
-            ShellCommand::Builtin(_) => {
-                let mut command = Command::new(current_exe().unwrap());
+        let mut redirected_streams = RedirectStreams::standard();
+        if let Some(read) = previous_read.take() {
+            redirected_streams
+                .descriptors
+                .insert(0, Descriptor::File(read));
+        }
 
-                if is_first {
-                    command.stdin(Stdio::inherit());
-                } else {
-                    command.stdin(childs[index - 1].stdout.take().unwrap());
+        if !is_last {
+            match pipe() {
+                Ok((read, write)) => {
+                    previous_read = Some(read);
+                    redirected_streams
+                        .descriptors
+                        .insert(1, Descriptor::File(write));
+                }
+                Err(error) => {
+                    eprintln!("pipe: {}", describe_error(&error));
+                    exit(1);
                 }
+            }
+        }
+
+        let ast::Command::Simple(parsed_line) = command else {
+            let child = fork_stage(shell, redirected_streams, &mut previous_read, |shell| {
+                run_command(shell, command)
+            });
+
+            stages.push(Stage::Running(child));
+            continue;
+        };
+
+        let parsed_line = &match expand_simple_command(shell, parsed_line) {
+            Ok(parsed_line) => parsed_line,
+            Err(message) => {
+                eprintln!("{}", message);
+                stages.push(Stage::Done(1));
+                continue;
+            }
+        };
+        let redirected_streams = match redirected_streams.open(&parsed_line.redirects) {
+            Ok(redirected_streams) => redirected_streams,
+            Err(message) => {
+                eprintln!("{}", message);
+                stages.push(Stage::Done(1));
+                continue;
+            }
+        };
+
+        let Some(program) = parsed_line.arguments.first() else {
+            stages.push(Stage::Done(0));
+            continue;
+        };
+
+        let mut command = match shell.query(program) {
+            ShellCommand::Function(body) => {
+                let child = fork_stage(shell, redirected_streams, &mut previous_read, |shell| {
+                    run_function(
+                        shell,
+                        &body,
+                        &parsed_line.arguments,
+                        &parsed_line.assignments,
+                    )
+                });
 
-                let mut arguments: Vec<String> = Vec::new();
-                arguments.push("--builtin".into());
-                arguments.extend(parsed_line.arguments.clone());
-
-                let child = command
-                    .args(arguments)
-                    .stdout(if let Some(file) = redirected_streams.output {
-                        From::<File>::from(file)
-                    } else if is_last {
-                        Stdio::inherit()
-                    } else {
-                        Stdio::piped()
-                    })
-                    .stderr(if let Some(file) = redirected_streams.error {
-                        From::<File>::from(file)
-                    } else {
-                        Stdio::inherit()
-                    })
-                    .spawn()
-                    .expect("failed to execute process");
-
-                childs.push(Box::new(child));
+                stages.push(Stage::Running(child));
+                continue;
+            }
+            ShellCommand::Builtin(builtin) => {
+                let child = fork_stage(shell, redirected_streams, &mut previous_read, |shell| {
+                    let mut standard = RedirectStreams::standard();
+                    run_builtin(shell, builtin, &parsed_line.arguments, &mut standard)
+                });
+
+                stages.push(Stage::Running(child));
+                continue;
             }
             ShellCommand::Executable(path) => {
                 let mut command = Command::new(path);
@@ -108,36 +339,49 @@ fn _do_run_pipeline(shell: &mut Shell, parsed_lines: Vec<ParsedLine>) {
                 #[cfg(unix)]
                 command.arg0(&parsed_line.arguments[0]);
 
-                if is_first {
-                    command.stdin(Stdio::inherit());
-                } else {
-                    command.stdin(childs[index - 1].stdout.take().unwrap());
-                }
+                command.args(&parsed_line.arguments[1..]);
+                command
+            }
+            ShellCommand::None => {
+                eprintln!("{}: command not found", program);
+                stages.push(Stage::Done(STATUS_NOT_FOUND));
+                continue;
+            }
+        };
 
-                let child = command
-                    .args(&parsed_line.arguments[1..])
-                    .stdout(if let Some(file) = redirected_streams.output {
-                        From::<File>::from(file)
-                    } else if is_last {
-                        Stdio::inherit()
-                    } else {
-                        Stdio::piped()
-                    })
-                    .stderr(if let Some(file) = redirected_streams.error {
-                        From::<File>::from(file)
-                    } else {
-                        Stdio::inherit()
-                    })
-                    .spawn()
-                    .expect("failed to execute process");
-
-                childs.push(Box::new(child));
+        command
+            .env_clear()
+            .envs(shell.variables.exported())
+            .envs(environment(&parsed_line.assignments));
+
+        redirected_streams.configure(&mut command);
+
+        match command.spawn() {
+            Ok(child) => stages.push(Stage::Running(child.id() as i32)),
+            Err(error) => {
+                eprintln!("{}: {}", program, describe_error(&error));
+                stages.push(Stage::Done(STATUS_NOT_EXECUTABLE));
             }
-            ShellCommand::None => eprintln!("{}: command not found", program),
         }
     }
 
-    childs.last_mut().unwrap().wait().unwrap();
+    drop(previous_read);
+
+    let statuses: Vec<i32> = stages
+        .into_iter()
+        .map(|stage| match stage {
+            Stage::Running(pid) => wait_for(pid),
+            Stage::Done(status) => status,
+        })
+        .collect();
+
+    let status = match shell.set_option("pipefail") {
+        true => statuses.iter().rev().find(|status| **status != 0),
+        false => statuses.last(),
+    };
+
+    let reported: Vec<String> = statuses.iter().map(|status| status.to_string()).collect();
+    let _ = report.write_all(reported.join(" ").as_bytes());
 
-    exit(0);
+    exit(status.copied().unwrap_or(0));
 }
@@ -1,18 +1,52 @@
 use crate::*;
 use std::{
+    collections::{BTreeMap, HashMap},
     env,
+    ffi::{CStr, CString},
     io::Write,
     path::{Path, PathBuf},
+    process,
+    rc::Rc,
 };
 pub enum ShellCommand {
+    Function(Rc<Command>),
     Builtin(BuiltinFunction),
     Executable(PathBuf),
     None,
 }
 
+/// A pending jump out of the commands being run, counted in enclosing loops.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Break(usize),
+    Continue(usize),
+    Return,
+}
+
 pub struct Shell {
     pub builtins: BuiltinMap,
+    pub functions: HashMap<String, Rc<Command>>,
     pub history: Vec<String>,
+    pub variables: Variables,
+    pub name: String,
+    pub positional_parameters: Vec<String>,
+    pub pid: u32,
+    pub last_status: i32,
+    pub last_background_pid: Option<i32>,
+    pub pipe_status: Vec<i32>,
+    pub jobs: Jobs,
+    pub interactive: bool,
+    pub terminal: Option<Terminal>,
+    pub traps: Traps,
+    pub substitution_status: Option<i32>,
+    /// Set when `${name:?}` fails, which makes a non-interactive shell exit.
+    pub parameter_error: bool,
+    pub options: BTreeMap<&'static str, bool>,
+    pub set_options: BTreeMap<&'static str, bool>,
+    pub flow: Option<Flow>,
+    pub loop_depth: usize,
+    pub function_depth: usize,
+    pub condition_depth: usize,
     last_history_append_index: usize,
 }
 
@@ -25,10 +59,44 @@ impl Shell {
         builtins.insert("pwd".into(), builtin_pwd);
         builtins.insert("cd".into(), builtin_cd);
         builtins.insert("history".into(), builtin_history);
+        builtins.insert("export".into(), builtin_export);
+        builtins.insert("unset".into(), builtin_unset);
+        builtins.insert("readonly".into(), builtin_readonly);
+        builtins.insert("shopt".into(), builtin_shopt);
+        builtins.insert("break".into(), builtin_break);
+        builtins.insert("continue".into(), builtin_continue);
+        builtins.insert("local".into(), builtin_local);
+        builtins.insert("return".into(), builtin_return);
+        builtins.insert("wait".into(), builtin_wait);
+        builtins.insert("jobs".into(), builtin_jobs);
+        builtins.insert("fg".into(), builtin_fg);
+        builtins.insert("bg".into(), builtin_bg);
+        builtins.insert("trap".into(), builtin_trap);
+        builtins.insert("set".into(), builtin_set);
 
         let mut shell = Shell {
             builtins,
+            functions: HashMap::new(),
             history: Vec::new(),
+            variables: Variables::from_environment(),
+            name: env::args().next().unwrap_or_default(),
+            positional_parameters: Vec::new(),
+            pid: process::id(),
+            last_status: 0,
+            last_background_pid: None,
+            pipe_status: vec![0],
+            jobs: Jobs::default(),
+            interactive: false,
+            terminal: None,
+            traps: Traps::default(),
+            substitution_status: None,
+            parameter_error: false,
+            options: BTreeMap::from([("failglob", false), ("nullglob", false)]),
+            set_options: BTreeMap::from([("pipefail", false)]),
+            flow: None,
+            loop_depth: 0,
+            function_depth: 0,
+            condition_depth: 0,
             last_history_append_index: 0,
         };
 
@@ -40,11 +108,15 @@ impl Shell {
     }
 
     pub fn query(&self, program: &String) -> ShellCommand {
+        if let Some(body) = self.functions.get(program.as_str()) {
+            return ShellCommand::Function(body.clone());
+        }
+
         if let Some(builtin) = self.builtins.get(program.as_str()) {
             return ShellCommand::Builtin(*builtin);
         }
 
-        if let Ok(paths) = env::var("PATH") {
+        if let Some(paths) = self.variables.get("PATH") {
             for directory in paths.split(":") {
                 let path = Path::new(directory).join(program);
 
@@ -57,9 +129,49 @@ impl Shell {
         return ShellCommand::None;
     }
 
+    pub fn get_parameter(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background_pid.map(|pid| pid.to_string()),
+            "0" => Some(self.name.clone()),
+            "#" => Some(self.positional_parameters.len().to_string()),
+            "@" | "*" => Some(self.positional_parameters.join(" ")),
+            "PIPESTATUS" => self.pipe_status.first().map(|status| status.to_string()),
+            _ if name.chars().all(|character| character.is_ascii_digit()) => {
+                let index = name.parse::<usize>().ok()?;
+                self.positional_parameters
+                    .get(index.checked_sub(1)?)
+                    .cloned()
+            }
+            _ => self.variables.get(name).cloned(),
+        }
+    }
+
+    /// The elements of an array parameter, a set scalar counting as an array of one element.
+    pub fn get_array(&self, name: &str) -> Vec<String> {
+        match name {
+            "@" | "*" => self.positional_parameters.clone(),
+            "PIPESTATUS" => self
+                .pipe_status
+                .iter()
+                .map(|status| status.to_string())
+                .collect(),
+            _ => self.get_parameter(name).into_iter().collect(),
+        }
+    }
+
+    pub fn option(&self, name: &str) -> bool {
+        self.options.get(name).copied().unwrap_or(false)
+    }
+
+    pub fn set_option(&self, name: &str) -> bool {
+        self.set_options.get(name).copied().unwrap_or(false)
+    }
+
     pub fn get_history_file(&self) -> Option<String> {
-        if let Ok(histfile) = env::var("HISTFILE") {
-            let path = Path::new(&histfile);
+        if let Some(histfile) = self.variables.get("HISTFILE") {
+            let path = Path::new(histfile);
             if path.exists() {
                 return Some(path.to_path_buf().to_str().unwrap().to_string());
             }
@@ -98,9 +210,44 @@ impl Shell {
         }
     }
 
-    pub fn finish(&mut self) {
+    /// Reports a failed expansion, returning the code to exit with if the shell
+    /// should stop there.
+    pub fn expansion_error(&mut self, message: &str) -> Option<i32> {
+        eprintln!("{}", message);
+        self.last_status = 1;
+
+        match std::mem::take(&mut self.parameter_error) && !self.interactive {
+            true => Some(1),
+            false => None,
+        }
+    }
+
+    /// Runs the `EXIT` trap and saves the history, returning the code to exit with.
+    pub fn finish(&mut self, code: i32) -> i32 {
+        let code = run_exit_trap(self, code);
+
         if let Some(path) = self.get_history_file() {
             self.write_history(&path);
         }
+
+        code
     }
 }
+
+/// Looks up the home directory of `user`, or of the current user, in the passwd database.
+pub fn home_directory(user: Option<&str>) -> Option<String> {
+    let entry = match user {
+        Some(user) => {
+            let name = CString::new(user).ok()?;
+            unsafe { libc::getpwnam(name.as_ptr()) }
+        }
+        None => unsafe { libc::getpwuid(libc::getuid()) },
+    };
+
+    if entry.is_null() {
+        return None;
+    }
+
+    let directory = unsafe { CStr::from_ptr((*entry).pw_dir) };
+    Some(directory.to_string_lossy().into_owned())
+}
@@ -1,58 +1,309 @@
 use std::{
-    fs::File,
-    io::{self, Write},
+    collections::BTreeMap,
+    env,
+    ffi::CStr,
+    fs::{self, File},
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        process::CommandExt,
+    },
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::{Redirect, StandardNamedStream};
+use crate::{Redirect, RedirectTarget};
 
-pub struct RedirectStreams {
-    pub output: Option<File>,
-    pub error: Option<File>,
+pub fn describe_error(error: &io::Error) -> String {
+    match error.raw_os_error() {
+        Some(code) => unsafe { CStr::from_ptr(libc::strerror(code)) }
+            .to_string_lossy()
+            .into_owned(),
+        None => error.to_string(),
+    }
 }
 
-impl RedirectStreams {
-    pub fn new(redirects: &Vec<Redirect>) -> Result<RedirectStreams, io::Error> {
-        let mut output: Option<File> = None;
-        let mut error: Option<File> = None;
+fn here_document_file(content: &str) -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
 
-        for redirect in redirects {
-            let file = File::options()
+    let path = env::temp_dir().join(format!(
+        "shell-here-document-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+
+    fs::remove_file(&path)?;
+
+    file.write_all(content.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(file)
+}
+
+fn open_target(target: &RedirectTarget) -> Result<File, String> {
+    let (path, result) = match target {
+        RedirectTarget::Input { path } => (path.as_str(), File::open(path)),
+        RedirectTarget::Output { path, append } => (
+            path.as_str(),
+            File::options()
                 .create(true)
-                .truncate(!redirect.append)
-                .append(redirect.append)
+                .truncate(!append)
+                .append(*append)
                 .write(true)
-                .open(redirect.path.clone())?;
+                .open(path),
+        ),
+        RedirectTarget::ReadWrite { path } => (
+            path.as_str(),
+            File::options()
+                .create(true)
+                .truncate(false)
+                .read(true)
+                .write(true)
+                .open(path),
+        ),
+        RedirectTarget::Duplicate { .. } => unreachable!("duplications are not opened"),
+        RedirectTarget::HereDocument { body, .. } => ("here-document", here_document_file(body)),
+        RedirectTarget::HereString { word } => {
+            ("here-string", here_document_file(&format!("{}\n", word)))
+        }
+    };
+
+    result.map_err(|error| format!("{}: {}", path, describe_error(&error)))
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(result)
+}
+
+fn duplicate_above(fd: RawFd, lowest: RawFd) -> io::Result<File> {
+    let duplicated = check(unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, lowest) })?;
 
-            match redirect.stream_name {
-                StandardNamedStream::Output => output = Some(file),
-                StandardNamedStream::Error => error = Some(file),
-                StandardNamedStream::Unknown => {}
+    Ok(unsafe { File::from_raw_fd(duplicated) })
+}
+
+fn duplicate_inherited(fd: u32) -> io::Result<File> {
+    duplicate_above(fd as RawFd, 0)
+}
+
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds: [RawFd; 2] = [-1; 2];
+    check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    for file in [&read, &write] {
+        check(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) })?;
+    }
+
+    Ok((read, write))
+}
+
+/// Where descriptors the shell keeps for itself go, out of the way of what scripts usually use.
+pub const SHELL_DESCRIPTOR_BASE: RawFd = 10;
+
+/// Descriptors of the shell process that were replaced by [`RedirectStreams::apply`].
+pub struct SavedDescriptors {
+    previous: Vec<(RawFd, Option<File>)>,
+}
+
+impl SavedDescriptors {
+    pub fn restore(self) {
+        let _ = io::stdout().flush();
+
+        for (fd, previous) in self.previous.into_iter().rev() {
+            match previous {
+                Some(file) => unsafe { libc::dup2(file.as_raw_fd(), fd) },
+                None => unsafe { libc::close(fd) },
             };
         }
+    }
+}
+
+pub enum Descriptor {
+    File(File),
+    Closed,
+}
+
+pub struct RedirectStreams {
+    pub descriptors: BTreeMap<u32, Descriptor>,
+}
 
-        return Ok(RedirectStreams { output, error });
+impl RedirectStreams {
+    pub fn new(redirects: &[Redirect]) -> Result<RedirectStreams, String> {
+        RedirectStreams::standard().open(redirects)
     }
 
     pub fn standard() -> RedirectStreams {
         RedirectStreams {
-            output: None,
-            error: None,
+            descriptors: BTreeMap::new(),
+        }
+    }
+
+    pub fn open(mut self, redirects: &[Redirect]) -> Result<RedirectStreams, String> {
+        for redirect in redirects {
+            let descriptor = match &redirect.target {
+                RedirectTarget::Duplicate { word } if word == "-" => Descriptor::Closed,
+                RedirectTarget::Duplicate { word } => match word.parse::<u32>() {
+                    Ok(fd) => self.duplicate(fd)?,
+                    Err(_) => return Err(format!("{}: ambiguous redirect", word)),
+                },
+                target => Descriptor::File(open_target(target)?),
+            };
+
+            self.descriptors.insert(redirect.fd, descriptor);
         }
+
+        Ok(self)
+    }
+
+    fn duplicate(&self, fd: u32) -> Result<Descriptor, String> {
+        let result = match self.descriptors.get(&fd) {
+            Some(Descriptor::File(file)) => file.try_clone(),
+            Some(Descriptor::Closed) => Err(io::Error::from_raw_os_error(libc::EBADF)),
+            None => duplicate_inherited(fd),
+        };
+
+        result
+            .map(Descriptor::File)
+            .map_err(|error| format!("{}: {}", fd, describe_error(&error)))
     }
 
-    pub fn println(&mut self, message: &str) {
-        if let Some(file) = &mut self.output {
-            writeln!(file, "{message}").expect("could not print to redirected stdout");
-        } else {
-            println!("{message}");
+    fn stdio(&mut self, fd: u32) -> Stdio {
+        match self.descriptors.remove(&fd) {
+            Some(Descriptor::File(file)) => Stdio::from(file),
+            Some(Descriptor::Closed) => {
+                self.descriptors.insert(fd, Descriptor::Closed);
+                Stdio::inherit()
+            }
+            None => Stdio::inherit(),
         }
     }
 
-    pub fn println_error(&mut self, message: &str) {
-        if let Some(file) = &mut self.error {
-            writeln!(file, "{message}").expect("could not print to redirected stderr");
-        } else {
-            eprintln!("{message}");
+    /// Hands the standard streams to `command` and arranges for every other
+    /// descriptor to be moved into place (or closed) in the child before exec.
+    pub fn configure(mut self, command: &mut Command) {
+        command
+            .stdin(self.stdio(0))
+            .stdout(self.stdio(1))
+            .stderr(self.stdio(2));
+
+        if self.descriptors.is_empty() {
+            return;
         }
+
+        let descriptors: Vec<(u32, Descriptor)> = self.descriptors.into_iter().collect();
+        let lowest_free = descriptors
+            .iter()
+            .map(|(fd, _)| *fd as RawFd + 1)
+            .max()
+            .unwrap_or(0);
+        let mut temporaries: Vec<RawFd> = vec![-1; descriptors.len()];
+
+        let setup = move || {
+            // move sources out of the way first so a target never overwrites a pending source
+            for (index, (_, descriptor)) in descriptors.iter().enumerate() {
+                if let Descriptor::File(file) = descriptor {
+                    temporaries[index] = check(unsafe {
+                        libc::fcntl(file.as_raw_fd(), libc::F_DUPFD_CLOEXEC, lowest_free)
+                    })?;
+                }
+            }
+
+            for (index, (fd, descriptor)) in descriptors.iter().enumerate() {
+                match descriptor {
+                    Descriptor::File(_) => {
+                        check(unsafe { libc::dup2(temporaries[index], *fd as RawFd) })?;
+                    }
+                    Descriptor::Closed => unsafe {
+                        libc::close(*fd as RawFd);
+                    },
+                }
+            }
+
+            Ok(())
+        };
+
+        unsafe { command.pre_exec(setup) };
+    }
+
+    /// Moves every descriptor into place in the shell process itself, for commands
+    /// that run without forking, and returns what is needed to put the old ones back.
+    ///
+    /// The saved copies only ever take descriptors that are free, so they never reuse the
+    /// terminal's, which callers move out of the way of the ones replaced here.
+    pub fn apply(self) -> io::Result<SavedDescriptors> {
+        let _ = io::stdout().flush();
+
+        let lowest_free = self
+            .descriptors
+            .keys()
+            .map(|fd| *fd as RawFd + 1)
+            .max()
+            .unwrap_or(0)
+            .max(SHELL_DESCRIPTOR_BASE);
+
+        let mut sources = Vec::new();
+        for (fd, descriptor) in self.descriptors {
+            let source = match descriptor {
+                Descriptor::File(file) => Some(duplicate_above(file.as_raw_fd(), lowest_free)?),
+                Descriptor::Closed => None,
+            };
+
+            sources.push((fd as RawFd, source));
+        }
+
+        let mut saved = SavedDescriptors {
+            previous: Vec::new(),
+        };
+
+        for (fd, source) in sources {
+            let previous = match duplicate_above(fd, lowest_free) {
+                Ok(previous) => Some(previous),
+                Err(error) if error.raw_os_error() == Some(libc::EBADF) => None,
+                Err(error) => {
+                    saved.restore();
+                    return Err(error);
+                }
+            };
+            saved.previous.push((fd, previous));
+
+            let result = match source {
+                Some(file) => check(unsafe { libc::dup2(file.as_raw_fd(), fd) }),
+                None => Ok(unsafe { libc::close(fd) }),
+            };
+
+            if let Err(error) = result {
+                saved.restore();
+                return Err(error);
+            }
+        }
+
+        Ok(saved)
+    }
+
+    fn write_line(&mut self, fd: u32, message: &str) -> io::Result<()> {
+        match self.descriptors.get_mut(&fd) {
+            Some(Descriptor::File(file)) => writeln!(file, "{message}"),
+            Some(Descriptor::Closed) => Err(io::Error::from_raw_os_error(libc::EBADF)),
+            None if fd == 1 => writeln!(io::stdout(), "{message}"),
+            None => writeln!(io::stderr(), "{message}"),
+        }
+    }
+
+    pub fn println(&mut self, message: &str) -> io::Result<()> {
+        self.write_line(1, message)
+    }
+
+    pub fn println_error(&mut self, message: &str) -> io::Result<()> {
+        self.write_line(2, message)
     }
 }
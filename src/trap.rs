@@ -0,0 +1,215 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::shell_ignores;
+
+/// Signals delivered since the last command boundary, one bit per signal number.
+static PENDING_SIGNALS: AtomicU64 = AtomicU64::new(0);
+
+const SIGNAL_NAMES: [(libc::c_int, &str); 29] = [
+    (libc::SIGHUP, "HUP"),
+    (libc::SIGINT, "INT"),
+    (libc::SIGQUIT, "QUIT"),
+    (libc::SIGILL, "ILL"),
+    (libc::SIGTRAP, "TRAP"),
+    (libc::SIGABRT, "ABRT"),
+    (libc::SIGBUS, "BUS"),
+    (libc::SIGFPE, "FPE"),
+    (libc::SIGKILL, "KILL"),
+    (libc::SIGUSR1, "USR1"),
+    (libc::SIGSEGV, "SEGV"),
+    (libc::SIGUSR2, "USR2"),
+    (libc::SIGPIPE, "PIPE"),
+    (libc::SIGALRM, "ALRM"),
+    (libc::SIGTERM, "TERM"),
+    (libc::SIGCHLD, "CHLD"),
+    (libc::SIGCONT, "CONT"),
+    (libc::SIGSTOP, "STOP"),
+    (libc::SIGTSTP, "TSTP"),
+    (libc::SIGTTIN, "TTIN"),
+    (libc::SIGTTOU, "TTOU"),
+    (libc::SIGURG, "URG"),
+    (libc::SIGXCPU, "XCPU"),
+    (libc::SIGXFSZ, "XFSZ"),
+    (libc::SIGVTALRM, "VTALRM"),
+    (libc::SIGPROF, "PROF"),
+    (libc::SIGWINCH, "WINCH"),
+    (libc::SIGIO, "IO"),
+    (libc::SIGSYS, "SYS"),
+];
+
+/// What a trap is attached to, in the order `trap` lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrapCondition {
+    Exit,
+    Signal(libc::c_int),
+    Debug,
+    Err,
+    Return,
+}
+
+impl TrapCondition {
+    /// Accepts `EXIT`, `ERR`, `DEBUG`, `RETURN`, signal names with or without `SIG` and signal numbers.
+    pub fn parse(spec: &str) -> Option<TrapCondition> {
+        if let Ok(number) = spec.parse::<libc::c_int>() {
+            return match number {
+                0 => Some(TrapCondition::Exit),
+                _ => signal_by_number(number).map(|_| TrapCondition::Signal(number)),
+            };
+        }
+
+        let name = spec.to_ascii_uppercase();
+        match name.as_str() {
+            "EXIT" => Some(TrapCondition::Exit),
+            "DEBUG" => Some(TrapCondition::Debug),
+            "ERR" => Some(TrapCondition::Err),
+            "RETURN" => Some(TrapCondition::Return),
+            _ => {
+                let name = name.strip_prefix("SIG").unwrap_or(&name);
+
+                SIGNAL_NAMES
+                    .iter()
+                    .find(|(_, candidate)| *candidate == name)
+                    .map(|&(signal, _)| TrapCondition::Signal(signal))
+            }
+        }
+    }
+}
+
+impl fmt::Display for TrapCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrapCondition::Exit => write!(f, "EXIT"),
+            TrapCondition::Signal(signal) => match signal_by_number(*signal) {
+                Some(name) => write!(f, "SIG{}", name),
+                None => write!(f, "{}", signal),
+            },
+            TrapCondition::Debug => write!(f, "DEBUG"),
+            TrapCondition::Err => write!(f, "ERR"),
+            TrapCondition::Return => write!(f, "RETURN"),
+        }
+    }
+}
+
+fn signal_by_number(signal: libc::c_int) -> Option<&'static str> {
+    SIGNAL_NAMES
+        .iter()
+        .find(|(number, _)| *number == signal)
+        .map(|&(_, name)| name)
+}
+
+extern "C" fn record_signal(signal: libc::c_int) {
+    PENDING_SIGNALS.fetch_or(1 << signal as u64, Ordering::SeqCst);
+}
+
+fn set_disposition(signal: libc::c_int, handler: libc::sighandler_t) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+/// The commands registered with `trap`, an empty one meaning the condition is ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Traps {
+    actions: BTreeMap<TrapCondition, String>,
+    /// Set while a handler runs, so that it does not trigger `DEBUG`, `ERR` or itself again.
+    pub running: bool,
+}
+
+impl Traps {
+    pub fn get(&self, condition: TrapCondition) -> Option<&String> {
+        self.actions.get(&condition)
+    }
+
+    /// Tells whether `trap '' signal` made the shell and its children ignore `signal`.
+    pub fn ignores(&self, signal: libc::c_int) -> bool {
+        self.get(TrapCondition::Signal(signal))
+            .is_some_and(|action| action.is_empty())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TrapCondition, &String)> {
+        self.actions.iter()
+    }
+
+    pub fn set(&mut self, condition: TrapCondition, action: String) {
+        if let TrapCondition::Signal(signal) = condition {
+            let handler = match action.is_empty() {
+                true => libc::SIG_IGN,
+                false => record_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            };
+
+            set_disposition(signal, handler);
+        }
+
+        self.actions.insert(condition, action);
+    }
+
+    /// Removes a trap, putting the signal back to how the shell handles it by itself.
+    pub fn reset(&mut self, condition: TrapCondition, interactive: bool) {
+        if self.actions.remove(&condition).is_none() {
+            return;
+        }
+
+        if let TrapCondition::Signal(signal) = condition {
+            let handler = match shell_ignores(signal, interactive) {
+                true => libc::SIG_IGN,
+                false => libc::SIG_DFL,
+            };
+
+            set_disposition(signal, handler);
+        }
+    }
+
+    /// Forgets the traps that run commands when entering a subshell, keeping the ignored signals ignored.
+    pub fn enter_subshell(&mut self) {
+        let caught: Vec<TrapCondition> = self
+            .actions
+            .iter()
+            .filter(|(_, action)| !action.is_empty())
+            .map(|(condition, _)| *condition)
+            .collect();
+
+        for condition in caught {
+            self.reset(condition, false);
+        }
+
+        PENDING_SIGNALS.store(0, Ordering::SeqCst);
+    }
+
+    /// Records `signal` as received by the shell, if trapped, when only the foreground job got it.
+    pub fn notice(&self, signal: libc::c_int) {
+        let caught = self.get(TrapCondition::Signal(signal));
+
+        if caught.is_some_and(|action| !action.is_empty()) {
+            PENDING_SIGNALS.fetch_or(1 << signal as u64, Ordering::SeqCst);
+        }
+    }
+
+    /// Takes the trapped signals received since the last call, lowest number first.
+    pub fn take_pending(&self) -> Vec<TrapCondition> {
+        let pending = PENDING_SIGNALS.swap(0, Ordering::SeqCst);
+
+        (1..u64::BITS as libc::c_int)
+            .filter(|signal| pending & (1 << *signal as u64) != 0)
+            .map(TrapCondition::Signal)
+            .collect()
+    }
+}
+
+/// Lists the signals `trap -l` knows about.
+pub fn signal_names() -> impl Iterator<Item = (libc::c_int, &'static str)> {
+    SIGNAL_NAMES.iter().copied()
+}
+
+/// Quotes `text` so that it reads back as a single word, as `trap -p` prints actions.
+pub fn quote_word(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
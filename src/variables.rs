@@ -0,0 +1,151 @@
+use std::{collections::BTreeMap, env};
+
+#[derive(Debug, Clone, Default)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    values: BTreeMap<String, Variable>,
+    scopes: Vec<Vec<(String, Option<Variable>)>>,
+}
+
+impl Variables {
+    pub fn from_environment() -> Variables {
+        let mut values = BTreeMap::new();
+
+        for (name, value) in env::vars() {
+            values.insert(
+                name,
+                Variable {
+                    value,
+                    exported: true,
+                    readonly: false,
+                },
+            );
+        }
+
+        Variables {
+            values,
+            scopes: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.values.get(name).map(|variable| &variable.value)
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.values.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.values.iter()
+    }
+
+    pub fn set(&mut self, name: &str, value: String) -> Result<(), String> {
+        let variable = self.values.entry(name.to_string()).or_default();
+        if variable.readonly {
+            return Err(format!("{}: readonly variable", name));
+        }
+
+        variable.value = value;
+        Ok(())
+    }
+
+    pub fn export(&mut self, name: &str, value: Option<String>) -> Result<(), String> {
+        if let Some(value) = value {
+            self.set(name, value)?;
+        }
+
+        self.values.entry(name.to_string()).or_default().exported = true;
+        Ok(())
+    }
+
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(variable) = self.values.get_mut(name) {
+            variable.exported = false;
+        }
+    }
+
+    pub fn mark_readonly(&mut self, name: &str, value: Option<String>) -> Result<(), String> {
+        if let Some(value) = value {
+            self.set(name, value)?;
+        }
+
+        self.values.entry(name.to_string()).or_default().readonly = true;
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), String> {
+        if let Some(variable) = self.values.get(name) {
+            if variable.readonly {
+                return Err(format!("{}: cannot unset: readonly variable", name));
+            }
+        }
+
+        self.values.remove(name);
+        Ok(())
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Leaves the innermost scope, putting back the variables its locals were shadowing.
+    pub fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+
+        for (name, previous) in scope.into_iter().rev() {
+            match previous {
+                Some(variable) => self.values.insert(name, variable),
+                None => self.values.remove(&name),
+            };
+        }
+    }
+
+    /// Declares `name` local to the innermost scope, which calls made from it can still see.
+    pub fn make_local(&mut self, name: &str, value: Option<String>) -> Result<(), String> {
+        let previous = self.values.get(name).cloned();
+        if previous.as_ref().is_some_and(|variable| variable.readonly) {
+            return Err(format!("{}: readonly variable", name));
+        }
+
+        let Some(scope) = self.scopes.last_mut() else {
+            return Err("can only be used in a function".to_string());
+        };
+
+        if !scope.iter().any(|(local, _)| local == name) {
+            scope.push((name.to_string(), previous));
+            self.values.remove(name);
+        }
+
+        match value {
+            Some(value) => self.set(name, value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn exported(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.values
+            .iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, variable)| (name, &variable.value))
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut characters = name.chars();
+
+    match characters.next() {
+        Some(character) if character == '_' || character.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+
+    characters.all(|character| character == '_' || character.is_ascii_alphanumeric())
+}